@@ -0,0 +1,33 @@
+// With the `tsc` feature, the `.d.ts` files vendored in `tsc/dts` are embedded
+// along with the typescript compiler, see `tsc/update.sh`.
+
+fn main() {
+    #[cfg(feature = "tsc")]
+    tsc::build();
+}
+
+#[cfg(feature = "tsc")]
+mod tsc {
+    use std::{env, fs, path::PathBuf};
+
+    pub fn build() {
+        println!("cargo:rerun-if-changed=tsc/dts");
+        let dts = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("tsc/dts");
+        let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+        let mut libs = Vec::new();
+        for entry in fs::read_dir(&dts).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            if name.starts_with("lib.") && name.ends_with(".d.ts") {
+                libs.push(format!("({:?}, include_str!({:?})),", name, path));
+            }
+        }
+        libs.sort();
+        fs::write(
+            out.join("tsc_libs.rs"),
+            format!("&[\n{}\n]", libs.join("\n")),
+        )
+        .unwrap();
+    }
+}
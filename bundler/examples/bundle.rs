@@ -13,7 +13,7 @@ async fn main() {
     let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/03_yoga_graphql.ts");
     let f = f.to_string_lossy().to_string();
     let m = resolve_url_or_path(&f).unwrap();
-    let (bundle, _) = deno_bundler::bundle(m, options).await.unwrap();
+    let output = deno_bundler::bundle(m, options).await.unwrap();
 
-    println!("{}", bundle);
+    println!("{}", output.code);
 }
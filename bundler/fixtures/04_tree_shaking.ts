@@ -0,0 +1,4 @@
+import { add } from './math.ts';
+import './unused.ts';
+
+console.log(add(1, 2));
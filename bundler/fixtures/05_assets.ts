@@ -0,0 +1,6 @@
+import css from './style.css';
+import logo from './logo.svg';
+
+export function render(): string {
+  return `<style>${css}</style>${logo}`;
+}
@@ -0,0 +1,9 @@
+if (process.env.NODE_ENV === 'production') {
+  console.log('production build');
+} else {
+  console.log('development build');
+}
+
+if (__DEV__) {
+  console.log('debug tools');
+}
@@ -0,0 +1,6 @@
+import { instantiate } from './add.wasm';
+
+instantiate().then((exports) => {
+  const { add } = exports as { add: (a: number, b: number) => number };
+  console.log(add(1, 2));
+});
@@ -0,0 +1,3 @@
+import { env, url } from './meta.ts';
+
+console.log(url, env, import.meta.main);
@@ -0,0 +1,4 @@
+const worker = new Worker(new URL('./worker.ts', import.meta.url), { type: 'module' });
+
+worker.onmessage = (e: MessageEvent<number>) => console.log(e.data);
+worker.postMessage(1);
@@ -0,0 +1,12 @@
+export function add(a: number, b: number): number {
+  return round(a + b);
+}
+
+export function sub(a: number, b: number): number {
+  return a - b;
+}
+
+// used by `add`, so it's kept although no other module imports it
+export function round(n: number): number {
+  return Math.round(n * 1e6) / 1e6;
+}
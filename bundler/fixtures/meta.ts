@@ -0,0 +1,3 @@
+// deno-lint-ignore no-explicit-any
+export const env = (import.meta as any).env;
+export const url = import.meta.url;
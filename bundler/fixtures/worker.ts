@@ -0,0 +1,5 @@
+import { add } from './math.ts';
+
+self.onmessage = (e: MessageEvent<number>) => {
+  self.postMessage(add(e.data, 1));
+};
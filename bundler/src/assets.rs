@@ -0,0 +1,270 @@
+use deno_ast::MediaType;
+use deno_core::futures::future::LocalBoxFuture;
+use deno_core::{anyhow::bail, error::AnyError, futures::FutureExt, serde_json, ModuleSpecifier};
+use deno_graph::source::{LoadFuture, LoadResponse, Loader};
+use deno_utils::UniversalModuleLoader;
+use std::collections::HashMap;
+
+/// How an imported asset is turned into a javascript module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetHandler {
+    /// `export default` the content as a string
+    Text,
+    /// `export default` the base64 encoded content
+    Base64,
+    /// `export default` a data url of the content
+    DataUrl,
+    /// `export default` the content as an `Uint8Array`
+    Bytes,
+    /// inject the content as a `<style>` into the document if there's one, and
+    /// `export default` the css string
+    Css,
+    /// `export default` a promise of the `WebAssembly.Module` compiled with
+    /// `WebAssembly.compile`, and export an async `instantiate(imports)`
+    /// function which resolves to the exports of a new instance
+    Wasm,
+}
+
+/// Which imports an asset rule applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetMatcher {
+    /// file extension without the leading dot, e.g. `svg`
+    Extension(String),
+    MediaType(MediaType),
+}
+
+/// Map the imports matched by `matcher` to an asset handler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetRule {
+    pub matcher: AssetMatcher,
+    pub handler: AssetHandler,
+}
+
+impl AssetRule {
+    pub fn extension(ext: impl AsRef<str>, handler: AssetHandler) -> Self {
+        let ext = ext.as_ref().trim_start_matches('.').to_lowercase();
+        Self {
+            matcher: AssetMatcher::Extension(ext),
+            handler,
+        }
+    }
+
+    pub fn media_type(media_type: MediaType, handler: AssetHandler) -> Self {
+        Self {
+            matcher: AssetMatcher::MediaType(media_type),
+            handler,
+        }
+    }
+
+    fn matches(&self, specifier: &ModuleSpecifier) -> bool {
+        match &self.matcher {
+            AssetMatcher::Extension(ext) => {
+                get_extension(specifier).as_deref() == Some(ext.as_str())
+            }
+            AssetMatcher::MediaType(media_type) => MediaType::from(specifier) == *media_type,
+        }
+    }
+}
+
+pub type LoadBytesFuture = LocalBoxFuture<'static, Result<Option<Vec<u8>>, AnyError>>;
+
+/// A graph loader which also loads the raw content of the assets, so that the
+/// binary assets, e.g. wasm, don't have to be valid utf-8 modules.
+pub trait AssetSource: Loader {
+    /// Load the raw content of an asset, `None` if it doesn't exist. By default
+    /// the asset is loaded as a module, so it must be utf-8.
+    fn load_bytes(&mut self, specifier: &ModuleSpecifier) -> LoadBytesFuture {
+        let response = self.load(specifier, false);
+        async move {
+            Ok(response.await?.and_then(|response| match response {
+                LoadResponse::Module { content, .. } => Some(content.as_bytes().to_vec()),
+                _ => None,
+            }))
+        }
+        .boxed_local()
+    }
+}
+
+/// Fetch the raw content of the assets from their urls, and put it into the
+/// module store like the modules.
+impl AssetSource for UniversalModuleLoader {
+    fn load_bytes(&mut self, specifier: &ModuleSpecifier) -> LoadBytesFuture {
+        let loader = self.clone();
+        let m = specifier.clone();
+        async move { loader.get_and_update_source_bytes(&m).await.map(Some) }.boxed_local()
+    }
+}
+
+/// A graph loader which loads the assets matched by the rules as javascript
+/// modules, and delegates everything else to the inner loader.
+pub struct AssetLoader<'a> {
+    inner: &'a mut dyn AssetSource,
+    rules: &'a [AssetRule],
+}
+
+impl<'a> AssetLoader<'a> {
+    /// The raw content of the assets is loaded from the inner source as well.
+    pub fn new(inner: &'a mut dyn AssetSource, rules: &'a [AssetRule]) -> Self {
+        Self { inner, rules }
+    }
+}
+
+impl Loader for AssetLoader<'_> {
+    fn load(&mut self, specifier: &ModuleSpecifier, is_dynamic: bool) -> LoadFuture {
+        let handler = match self.rules.iter().find(|r| r.matches(specifier)) {
+            Some(rule) => rule.handler,
+            None => return self.inner.load(specifier, is_dynamic),
+        };
+        let m = specifier.clone();
+        let bytes = self.inner.load_bytes(specifier);
+        async move {
+            let bytes = match bytes.await? {
+                Some(bytes) => bytes,
+                None => return Ok(None),
+            };
+            let code = handler.generate_module(&m, &bytes)?;
+            // let the graph treat the generated code as javascript regardless of
+            // the extension of the asset
+            let headers = HashMap::from([(
+                "content-type".to_string(),
+                "application/javascript".to_string(),
+            )]);
+            Ok(Some(LoadResponse::Module {
+                content: code.into(),
+                specifier: m,
+                maybe_headers: Some(headers),
+            }))
+        }
+        .boxed_local()
+    }
+}
+
+impl AssetHandler {
+    /// Generate the javascript module for the asset.
+    pub fn generate_module(
+        &self,
+        specifier: &ModuleSpecifier,
+        bytes: &[u8],
+    ) -> Result<String, AnyError> {
+        let code = match self {
+            AssetHandler::Text => format!("export default {};", to_text(specifier, bytes)?),
+            AssetHandler::Base64 => {
+                format!("export default \"{}\";", base64::encode(bytes))
+            }
+            AssetHandler::DataUrl => format!(
+                "export default \"data:{};base64,{}\";",
+                get_mime_type(specifier),
+                base64::encode(bytes)
+            ),
+            AssetHandler::Bytes => format!("{}\nexport default bytes;", decode_bytes(bytes)),
+            AssetHandler::Css => format!(
+                r#"const css = {};
+if (typeof document !== "undefined") {{
+  const style = document.createElement("style");
+  style.textContent = css;
+  document.head.appendChild(style);
+}}
+export default css;"#,
+                to_text(specifier, bytes)?
+            ),
+            AssetHandler::Wasm => format!(
+                r#"{}
+const wasmModule = WebAssembly.compile(bytes);
+export async function instantiate(imports = {{}}) {{
+  const instance = await WebAssembly.instantiate(await wasmModule, imports);
+  return instance.exports;
+}}
+export default wasmModule;"#,
+                decode_bytes(bytes)
+            ),
+        };
+        Ok(code)
+    }
+}
+
+/// Javascript code which decodes the content into an `Uint8Array` named
+/// `bytes`.
+fn decode_bytes(bytes: &[u8]) -> String {
+    format!(
+        r#"const data = atob("{}");
+const bytes = new Uint8Array(data.length);
+for (let i = 0; i < data.length; i++) bytes[i] = data.charCodeAt(i);"#,
+        base64::encode(bytes)
+    )
+}
+
+/// Convert the content into a javascript string literal.
+fn to_text(specifier: &ModuleSpecifier, bytes: &[u8]) -> Result<String, AnyError> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => Ok(serde_json::to_string(s)?),
+        Err(_) => bail!("Asset {} is not a valid utf-8 text.", specifier),
+    }
+}
+
+fn get_extension(specifier: &ModuleSpecifier) -> Option<String> {
+    let path = specifier.path();
+    let name = path.rsplit('/').next()?;
+    let (_, ext) = name.rsplit_once('.')?;
+    Some(ext.to_lowercase())
+}
+
+fn get_mime_type(specifier: &ModuleSpecifier) -> &'static str {
+    match get_extension(specifier).as_deref() {
+        Some("css") => "text/css",
+        Some("txt") => "text/plain",
+        Some("html") | Some("htm") => "text/html",
+        Some("json") => "application/json",
+        Some("wasm") => "application/wasm",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deno_core::resolve_url;
+
+    #[test]
+    fn asset_rule_should_match() {
+        let rule = AssetRule::extension(".SVG", AssetHandler::DataUrl);
+        assert!(rule.matches(&resolve_url("https://example.com/logo.svg?v=1").unwrap()));
+        assert!(!rule.matches(&resolve_url("https://example.com/svg/logo.png").unwrap()));
+        let rule = AssetRule::media_type(MediaType::Wasm, AssetHandler::Bytes);
+        assert!(rule.matches(&resolve_url("file:///tmp/add.wasm").unwrap()));
+    }
+
+    #[test]
+    fn asset_handler_should_generate_module() {
+        let m = resolve_url("file:///tmp/logo.svg").unwrap();
+        let code = AssetHandler::Text
+            .generate_module(&m, b"<svg>\"</svg>")
+            .unwrap();
+        assert_eq!(code, r#"export default "<svg>\"</svg>";"#);
+        let code = AssetHandler::DataUrl
+            .generate_module(&m, b"<svg/>")
+            .unwrap();
+        assert_eq!(
+            code,
+            r#"export default "data:image/svg+xml;base64,PHN2Zy8+";"#
+        );
+        assert!(AssetHandler::Css
+            .generate_module(&m, &[0xff, 0xfe])
+            .is_err());
+        let m = resolve_url("file:///tmp/add.wasm").unwrap();
+        let code = AssetHandler::Wasm
+            .generate_module(&m, b"\0asm\x01\0\0\0")
+            .unwrap();
+        assert!(code.contains(r#"atob("AGFzbQEAAAA=")"#));
+        assert!(code.contains("WebAssembly.compile(bytes)"));
+        assert!(code.contains("export async function instantiate"));
+        assert!(!code.contains("new WebAssembly."));
+    }
+}
@@ -0,0 +1,280 @@
+use clap::{ArgEnum, Parser};
+use deno_bundler::{
+    bundle, get_ts_config_with_file, BundleOptions, BundleOutput, BundleType, ConfigFile,
+    ConfigType,
+};
+use deno_core::{
+    anyhow::{bail, Context},
+    error::AnyError,
+    resolve_url_or_path,
+    serde_json::json,
+    ModuleSpecifier,
+};
+use import_map::ImportMap;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Format {
+    /// a single ES module
+    Esm,
+    /// a script wrapped in an immediately invoked function
+    Iife,
+    /// an ES module meant to be run as the main module
+    Main,
+}
+
+/// Bundle javascript/typescript modules for deno.
+#[derive(Debug, Parser)]
+#[clap(name = "deno-bundler", version, about)]
+struct Args {
+    /// Entry points, file paths or urls
+    #[clap(required = true)]
+    entries: Vec<String>,
+    /// Output file of a single entry. The bundle is printed to stdout if
+    /// neither --outfile nor --outdir is given
+    #[clap(short, long, value_name = "FILE", conflicts_with = "outdir")]
+    outfile: Option<PathBuf>,
+    /// Output directory, each entry is written to `<outdir>/<name>.js`
+    #[clap(short = 'd', long, value_name = "DIR")]
+    outdir: Option<PathBuf>,
+    #[clap(short, long, arg_enum, default_value = "esm")]
+    format: Format,
+    #[clap(long)]
+    minify: bool,
+    /// Write the source map next to the output, or inline it when printing to
+    /// stdout
+    #[clap(long)]
+    sourcemap: bool,
+    /// Imports kept as is, e.g. `react` or `https://esm.sh/*`
+    #[clap(long = "external", value_name = "SPECIFIER")]
+    externals: Vec<String>,
+    /// Compile time substitutions, e.g. `process.env.NODE_ENV='"production"'`
+    #[clap(long = "define", value_name = "KEY=VALUE", parse(try_from_str = parse_define))]
+    defines: Vec<(String, String)>,
+    /// Import map used to resolve the imports
+    #[clap(long, value_name = "FILE")]
+    import_map: Option<PathBuf>,
+    /// deno.json or tsconfig.json whose compilerOptions are used
+    #[clap(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Rebuild when the local input files change
+    #[clap(short, long)]
+    watch: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    if let Err(e) = run(args).await {
+        eprintln!("error: {:#}", e);
+        process::exit(1);
+    }
+}
+
+async fn run(args: Args) -> Result<(), AnyError> {
+    if args.entries.len() > 1 && args.outdir.is_none() {
+        bail!("Bundling multiple entries requires --outdir.");
+    }
+    let entries = args
+        .entries
+        .iter()
+        .map(|e| resolve_url_or_path(e).with_context(|| format!("Invalid entry {}", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if !args.watch {
+        let options = get_options(&args)?;
+        let (_, ok) = build(&args, &entries, &options).await;
+        if !ok {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    loop {
+        let mut inputs: Vec<PathBuf> = args
+            .config
+            .iter()
+            .chain(&args.import_map)
+            .cloned()
+            .collect();
+        match get_options(&args) {
+            Ok(options) => inputs.extend(build(&args, &entries, &options).await.0),
+            Err(e) => eprintln!("error: {:#}", e),
+        }
+        inputs.extend(entries.iter().filter_map(|e| e.to_file_path().ok()));
+        inputs.sort();
+        inputs.dedup();
+        eprintln!("Watching {} files for changes...", inputs.len());
+        wait_for_changes(&inputs).await;
+    }
+}
+
+fn get_options(args: &Args) -> Result<BundleOptions, AnyError> {
+    let maybe_config_file = args.config.as_ref().map(ConfigFile::read).transpose()?;
+    let (mut ts_config, maybe_ignored) =
+        get_ts_config_with_file(ConfigType::Bundle, maybe_config_file.as_ref())?;
+    if let Some(ignored) = maybe_ignored {
+        eprintln!("warning: {}", ignored);
+    }
+    if args.sourcemap {
+        let inline = args.outfile.is_none() && args.outdir.is_none();
+        ts_config.merge(&json!({
+            "sourceMap": !inline,
+            "inlineSourceMap": inline,
+            "inlineSources": true,
+        }));
+    }
+
+    let import_map = match &args.import_map {
+        Some(path) => Some(Arc::new(load_import_map(path)?)),
+        None => None,
+    };
+
+    Ok(BundleOptions {
+        bundle_type: match args.format {
+            Format::Esm => BundleType::Module,
+            Format::Iife => BundleType::Classic,
+            Format::Main => BundleType::MainModule,
+        },
+        ts_config,
+        minify: args.minify,
+        // the inputs of the metafile are watched
+        metafile: args.watch,
+        externals: args.externals.clone(),
+        define: args.defines.iter().cloned().collect(),
+        import_map,
+        ..BundleOptions::default()
+    })
+}
+
+/// Bundle all the entries, and return the local input files and whether all
+/// the entries are bundled successfully.
+async fn build(
+    args: &Args,
+    entries: &[ModuleSpecifier],
+    options: &BundleOptions,
+) -> (Vec<PathBuf>, bool) {
+    let mut inputs = Vec::new();
+    let mut ok = true;
+    for entry in entries {
+        let start = Instant::now();
+        let result = match bundle(entry.clone(), options.clone()).await {
+            Ok(output) => write_output(args, entry, &output).map(|_| output),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(output) => {
+                let metafiles = std::iter::once(&output)
+                    .chain(output.chunks.values())
+                    .filter_map(|o| o.maybe_metafile.as_ref());
+                for metafile in metafiles {
+                    inputs.extend(
+                        metafile
+                            .inputs
+                            .keys()
+                            .filter_map(|s| ModuleSpecifier::parse(s).ok())
+                            .filter_map(|s| s.to_file_path().ok()),
+                    );
+                }
+                eprintln!("Bundled {} in {:?}", entry, start.elapsed());
+            }
+            Err(e) => {
+                eprintln!("error: {:#}", e);
+                ok = false;
+            }
+        }
+    }
+    (inputs, ok)
+}
+
+fn write_output(
+    args: &Args,
+    entry: &ModuleSpecifier,
+    output: &BundleOutput,
+) -> Result<(), AnyError> {
+    let path = match (&args.outfile, &args.outdir) {
+        (Some(outfile), _) => outfile.clone(),
+        (None, Some(outdir)) => outdir.join(format!("{}.js", get_entry_name(entry))),
+        (None, None) => {
+            if !output.chunks.is_empty() {
+                bail!("Bundling workers requires --outfile or --outdir.");
+            }
+            println!("{}", output.code);
+            return Ok(());
+        }
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // the worker chunks are put next to the bundle
+    for (name, chunk) in &output.chunks {
+        write_file(&path.with_file_name(name), chunk)?;
+    }
+    write_file(&path, output)
+}
+
+fn write_file(path: &Path, output: &BundleOutput) -> Result<(), AnyError> {
+    let mut code = output.code.clone();
+    if let Some(map) = &output.maybe_map {
+        let map_path = PathBuf::from(format!("{}.map", path.display()));
+        fs::write(&map_path, map)
+            .with_context(|| format!("Unable to write {}", map_path.display()))?;
+        let map_name = map_path.file_name().unwrap_or_default().to_string_lossy();
+        code.push_str(&format!("\n//# sourceMappingURL={}\n", map_name));
+    }
+    fs::write(path, code).with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(())
+}
+
+fn get_entry_name(entry: &ModuleSpecifier) -> String {
+    let name = entry
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .unwrap_or_default();
+    let name = name.split_once('.').map(|(stem, _)| stem).unwrap_or(name);
+    if name.is_empty() {
+        "bundle".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+fn load_import_map(path: &Path) -> Result<ImportMap, AnyError> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Unable to read import map {}", path.display()))?;
+    let base = resolve_url_or_path(&path.to_string_lossy())?;
+    let result = import_map::parse_from_json(&base, &text)
+        .with_context(|| format!("Invalid import map {}", path.display()))?;
+    for diagnostic in result.diagnostics {
+        eprintln!("warning: {}", diagnostic);
+    }
+    Ok(result.import_map)
+}
+
+fn parse_define(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.trim().to_string(), v.to_string()))
+        .ok_or_else(|| format!("invalid define \"{}\", expected KEY=VALUE", s))
+}
+
+async fn wait_for_changes(inputs: &[PathBuf]) {
+    let get_mtimes = || -> Vec<Option<SystemTime>> {
+        inputs
+            .iter()
+            .map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+            .collect()
+    };
+    let mtimes = get_mtimes();
+    loop {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        if get_mtimes() != mtimes {
+            return;
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use deno_core::{
+    error::AnyError,
+    serde_json::{self, json},
+};
+use deno_graph::ModuleGraph;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::{
+    task::Progress, AssetHandler, AssetMatcher, AssetRule, BundleOptions, BundleOutput, BundleType,
+    ImportMeta, SideEffects, Tsc,
+};
+
+/// The key of the bundle in the cache. It's a hash of the sources of all the
+/// modules in the graph and the options which affect the output, so any change
+/// of them results in a new key.
+pub fn get_cache_key(graph: &ModuleGraph, options: &BundleOptions) -> Result<String, AnyError> {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    for (root, _) in &graph.roots {
+        hasher.update(root.as_str());
+        hasher.update([0u8]);
+    }
+
+    let mut modules = graph.modules();
+    modules.sort_by(|a, b| a.specifier.cmp(&b.specifier));
+    for m in modules {
+        hasher.update(m.specifier.as_str());
+        hasher.update([0u8]);
+        hasher.update(m.media_type.as_ts_extension());
+        hasher.update([0u8]);
+        if let Some(source) = &m.maybe_source {
+            hasher.update(Sha256::digest(source.as_bytes()));
+        }
+    }
+
+    hasher.update(serde_json::to_vec(&serialize_options(options))?);
+    Ok(format!("bundle:{:x}", hasher.finalize()))
+}
+
+/// Serialize the options which affect the output of the bundle, with the
+/// maps sorted so that the result is stable. The options are destructured
+/// without `..`, so that a new option doesn't compile until it's decided
+/// whether it's part of the key.
+fn serialize_options(options: &BundleOptions) -> serde_json::Value {
+    let BundleOptions {
+        bundle_type,
+        ts_config,
+        emit_ignore_directives,
+        // where the modules are loaded from, the sources are hashed instead
+        module_store: _,
+        minify,
+        metafile,
+        side_effects,
+        loaders,
+        define,
+        banner,
+        footer,
+        use_strict,
+        template,
+        type_check,
+        content_hash,
+        import_meta,
+        import_meta_props,
+        bundle_cache: _,
+        externals,
+        import_map,
+    } = options;
+    let sorted =
+        |map: &std::collections::HashMap<String, String>| map.iter().collect::<BTreeMap<_, _>>();
+    json!({
+        "bundleType": match bundle_type {
+            BundleType::MainModule => "mainModule",
+            BundleType::Module => "module",
+            BundleType::Classic => "classic",
+        },
+        "tsConfig": ts_config.0,
+        "emitIgnoreDirectives": emit_ignore_directives,
+        "minify": minify,
+        "metafile": metafile,
+        "sideEffects": side_effects.iter().map(|rule| json!({
+            "pattern": rule.pattern,
+            "free": rule.side_effects == SideEffects::Free,
+        })).collect::<Vec<_>>(),
+        "loaders": loaders.iter().map(serialize_asset_rule).collect::<Vec<_>>(),
+        "define": sorted(define),
+        "banner": banner,
+        "footer": footer,
+        "useStrict": use_strict,
+        "template": template,
+        "typeCheck": type_check.as_ref().map(|check| json!({
+            "tsc": match &check.tsc {
+                Tsc::Snapshot(snapshot) => json!({ "snapshot": digest(snapshot) }),
+                Tsc::Source(source) => json!({ "source": digest(source.as_bytes()) }),
+            },
+            "lib": check.lib,
+            "libs": check.libs.iter().map(|(k, v)| (k, digest(v.as_bytes()))).collect::<BTreeMap<_, _>>(),
+            "config": check.maybe_config_file.as_ref().map(|c| &c.json),
+        })),
+        "contentHash": content_hash,
+        "importMeta": match import_meta {
+            ImportMeta::Rewrite => json!("rewrite"),
+            ImportMeta::Preserve => json!("preserve"),
+            ImportMeta::Relative(base) => json!({ "relative": base.as_str() }),
+        },
+        "importMetaProps": sorted(import_meta_props),
+        "externals": externals,
+        "importMap": import_map.as_ref().map(|m| json!({
+            "baseUrl": m.base_url().as_str(),
+            "json": m.to_json(),
+        })),
+    })
+}
+
+fn serialize_asset_rule(rule: &AssetRule) -> serde_json::Value {
+    let handler = match rule.handler {
+        AssetHandler::Text => "text",
+        AssetHandler::Base64 => "base64",
+        AssetHandler::DataUrl => "dataUrl",
+        AssetHandler::Bytes => "bytes",
+        AssetHandler::Css => "css",
+        AssetHandler::Wasm => "wasm",
+    };
+    match &rule.matcher {
+        AssetMatcher::Extension(ext) => json!({ "extension": ext, "handler": handler }),
+        AssetMatcher::MediaType(media_type) => {
+            json!({ "mediaType": media_type, "handler": handler })
+        }
+    }
+}
+
+fn digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Bundle the graph, or return the cached output if the graph and the options
+/// haven't changed since the last time.
+pub async fn bundle_graph_cached(
+    graph: &ModuleGraph,
+    options: &BundleOptions,
+    progress: &Progress,
+) -> Result<BundleOutput, AnyError> {
+    let cache = match &options.bundle_cache {
+        Some(cache) => cache,
+        None => return crate::bundle_graph(graph, options, progress),
+    };
+    let key = get_cache_key(graph, options)?;
+    if let Ok(data) = cache.get(&key).await {
+        // a corrupted entry is simply overwritten
+        if let Ok(output) = serde_json::from_slice(&data) {
+            return Ok(output);
+        }
+    }
+    let output = crate::bundle_graph(graph, options, progress)?;
+    // the output is still good if it can't be cached
+    if let Err(e) = cache.put(key, &serde_json::to_vec(&output)?).await {
+        log::warn!("Failed to cache the bundle: {:#}", e);
+    }
+    Ok(output)
+}
@@ -0,0 +1,104 @@
+// Type check a module graph with the typescript compiler. `ts` must already be
+// defined globally, e.g. by evaluating `typescript.js` before this script.
+((globalThis) => {
+  const ASSETS = "asset:///";
+
+  // diagnostics which don't make sense for a deno module graph
+  const IGNORED_DIAGNOSTICS = [
+    // File is a CommonJS module; it may be converted to an ES6 module.
+    1208,
+    // 'await' expressions are only allowed at the top level of a file when
+    // that file is a module...
+    1375,
+    // Top-level 'await' expressions are only allowed when the 'module' option
+    // is set to 'esnext' or 'system'...
+    1378,
+    // An import path cannot end with a '.ts' extension.
+    2691,
+    // Cannot find module. Did you mean to set the 'moduleResolution' option?
+    2792,
+    // Cannot find the common subdirectory path for the input files.
+    5009,
+    // Could not find a declaration file for module.
+    7016,
+  ];
+
+  const CATEGORIES = ["warning", "error", "suggestion", "message"];
+
+  function check({ config, roots, files, resolutions, libs }) {
+    // make the supplied libraries available to the `lib` compiler option
+    for (const name of Object.keys(libs)) {
+      const m = /^lib\.(.+)\.d\.ts$/.exec(name);
+      if (m && !ts.libMap.has(m[1])) {
+        ts.libs.push(m[1]);
+        ts.libMap.set(m[1], name);
+      }
+    }
+
+    const { options, errors } = ts.convertCompilerOptionsFromJson(config, "");
+
+    const readFile = (name) =>
+      name.startsWith(ASSETS)
+        ? libs[name.slice(ASSETS.length)]
+        : files[name]?.source;
+
+    const host = {
+      fileExists: (name) => readFile(name) !== undefined,
+      readFile,
+      getSourceFile(name, languageVersion) {
+        const source = readFile(name);
+        return source === undefined
+          ? undefined
+          : ts.createSourceFile(name, source, languageVersion, false);
+      },
+      getDefaultLibFileName: (options) =>
+        ASSETS + ts.getDefaultLibFileName(options),
+      getDefaultLibLocation: () => ASSETS,
+      writeFile() {},
+      getCurrentDirectory: () => "",
+      getCanonicalFileName: (name) => name,
+      useCaseSensitiveFileNames: () => true,
+      getNewLine: () => "\n",
+      resolveModuleNames(names, containingFile) {
+        const resolved = resolutions[containingFile] ?? {};
+        return names.map((name) => {
+          const fileName = resolved[name];
+          if (fileName === undefined) {
+            return undefined;
+          }
+          return {
+            resolvedFileName: fileName,
+            extension: files[fileName].extension,
+            isExternalLibraryImport: false,
+          };
+        });
+      },
+    };
+
+    const program = ts.createProgram({ rootNames: roots, options, host });
+    return [...errors, ...ts.getPreEmitDiagnostics(program)]
+      .filter(({ code }) => !IGNORED_DIAGNOSTICS.includes(code))
+      .map(toDiagnostic);
+  }
+
+  function toDiagnostic({ code, category, messageText, file, start, length }) {
+    let range = {};
+    if (file && start !== undefined) {
+      range = {
+        start: file.getLineAndCharacterOfPosition(start),
+        end: file.getLineAndCharacterOfPosition(start + (length ?? 0)),
+      };
+    }
+    return {
+      code,
+      category: CATEGORIES[category],
+      message: ts.flattenDiagnosticMessageText(messageText, "\n"),
+      fileName: file?.fileName ?? null,
+      start: null,
+      end: null,
+      ...range,
+    };
+  }
+
+  globalThis.__bundlerTypeCheck = check;
+})(globalThis);
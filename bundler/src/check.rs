@@ -0,0 +1,339 @@
+use deno_ast::MediaType;
+use deno_core::{
+    error::AnyError, serde_json, serde_v8, v8, JsRuntime, ModuleSpecifier, RuntimeOptions, Snapshot,
+};
+use deno_graph::{ModuleGraph, Resolved};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::config::{get_ts_config_with_file, ConfigFile, ConfigType, TypeLib};
+
+/// The host which drives the typescript compiler.
+const CHECK_JS: &str = include_str!("check.js");
+
+/// Where the typescript compiler comes from.
+#[derive(Debug, Clone)]
+pub enum Tsc {
+    /// a startup snapshot created by [create_tsc_snapshot]
+    Snapshot(&'static [u8]),
+    /// the source of `typescript.js`, evaluated every time before checking
+    Source(Arc<str>),
+}
+
+/// The typescript compiler vendored in `tsc/`, see `tsc/update.sh`.
+#[cfg(feature = "tsc")]
+static TYPESCRIPT_JS: &str = include_str!("../tsc/typescript.js");
+
+/// The snapshot of the vendored typescript compiler, created the first time
+/// it's needed and shared by the process.
+#[cfg(feature = "tsc")]
+static TSC_SNAPSHOT: once_cell::sync::Lazy<Box<[u8]>> = once_cell::sync::Lazy::new(|| {
+    create_tsc_snapshot(TYPESCRIPT_JS).expect("the vendored typescript compiler is broken")
+});
+
+/// The standard and deno `lib.*.d.ts` files vendored in `tsc/dts`.
+#[cfg(feature = "tsc")]
+static TSC_LIBS: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/tsc_libs.rs"));
+
+#[cfg(feature = "tsc")]
+impl Tsc {
+    /// The typescript compiler embedded by the `tsc` feature.
+    pub fn embedded() -> Self {
+        Tsc::Snapshot(&TSC_SNAPSHOT[..])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeCheckOptions {
+    pub tsc: Tsc,
+    pub lib: TypeLib,
+    /// type libraries keyed by their file names, e.g. `lib.esnext.d.ts` or
+    /// `lib.deno.window.d.ts`. All the libraries referenced by `lib`, directly
+    /// or via `/// <reference lib="..." />`, must be supplied.
+    pub libs: HashMap<String, Arc<str>>,
+    /// user config whose `compilerOptions` are merged over the base config
+    pub maybe_config_file: Option<ConfigFile>,
+}
+
+#[cfg(feature = "tsc")]
+impl TypeCheckOptions {
+    /// Check with the embedded typescript compiler, its standard libraries,
+    /// e.g. `lib.esnext.d.ts`, and the deno libraries referenced by `lib`, e.g.
+    /// `lib.deno.window.d.ts`.
+    pub fn embedded(lib: TypeLib) -> Self {
+        Self {
+            tsc: Tsc::embedded(),
+            lib,
+            libs: TSC_LIBS
+                .iter()
+                .map(|(name, source)| (name.to_string(), Arc::from(*source)))
+                .collect(),
+            maybe_config_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticCategory {
+    Warning,
+    Error,
+    Suggestion,
+    Message,
+}
+
+/// Zero based line and character of a position in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// A diagnostic reported by the typescript compiler.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeDiagnostic {
+    pub code: u32,
+    pub category: DiagnosticCategory,
+    pub message: String,
+    /// specifier of the module the diagnostic belongs to
+    pub file_name: Option<String>,
+    pub start: Option<Position>,
+    pub end: Option<Position>,
+}
+
+/// All the diagnostics of a type check, it is also the error returned by
+/// [crate::bundle] when type checking fails.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeDiagnostics(pub Vec<TypeDiagnostic>);
+
+impl TypeDiagnostics {
+    pub fn has_errors(&self) -> bool {
+        self.0
+            .iter()
+            .any(|d| d.category == DiagnosticCategory::Error)
+    }
+}
+
+impl fmt::Display for TypeDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let category = match self.category {
+            DiagnosticCategory::Warning => "warning",
+            DiagnosticCategory::Error => "error",
+            DiagnosticCategory::Suggestion => "suggestion",
+            DiagnosticCategory::Message => "message",
+        };
+        write!(f, "TS{} [{}]: {}", self.code, category, self.message)?;
+        if let Some(file_name) = &self.file_name {
+            write!(f, "\n    at {}", file_name)?;
+            if let Some(start) = &self.start {
+                write!(f, ":{}:{}", start.line + 1, start.character + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TypeDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, d) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TypeDiagnostics {}
+
+/// Create a startup snapshot with the typescript compiler loaded, to be used
+/// by [Tsc::Snapshot].
+pub fn create_tsc_snapshot(typescript: &str) -> Result<Box<[u8]>, AnyError> {
+    let mut runtime = JsRuntime::new(RuntimeOptions {
+        will_snapshot: true,
+        ..Default::default()
+    });
+    runtime.execute_script("deno:typescript.js", typescript)?;
+    runtime.execute_script("deno:check.js", CHECK_JS)?;
+    let snapshot = runtime.snapshot();
+    let snapshot_slice: &[u8] = &*snapshot;
+    Ok(snapshot_slice.into())
+}
+
+#[derive(Debug, Serialize)]
+struct CheckFile<'a> {
+    source: &'a str,
+    extension: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct CheckRequest<'a> {
+    config: serde_json::Value,
+    roots: Vec<String>,
+    files: HashMap<String, CheckFile<'a>>,
+    resolutions: HashMap<String, HashMap<&'a str, String>>,
+    libs: HashMap<&'a str, &'a str>,
+}
+
+/// Type check all the modules in the graph with the typescript compiler.
+pub fn type_check(
+    graph: &ModuleGraph,
+    options: &TypeCheckOptions,
+) -> Result<TypeDiagnostics, AnyError> {
+    let (config, _) = get_ts_config_with_file(
+        ConfigType::Check {
+            lib: options.lib.clone(),
+            tsc_emit: false,
+        },
+        options.maybe_config_file.as_ref(),
+    )?;
+
+    // typescript tells the kind of a file by its extension, so the file names
+    // given to it might differ from the specifiers
+    let mut names = HashMap::new();
+    let mut files = HashMap::new();
+    for m in graph.modules() {
+        if let Some(source) = &m.maybe_source {
+            let name = tsc_name(&m.specifier, m.media_type);
+            names.insert(m.specifier.clone(), name.clone());
+            files.insert(
+                name,
+                CheckFile {
+                    source,
+                    extension: m.media_type.as_ts_extension(),
+                },
+            );
+        }
+    }
+
+    let mut resolutions = HashMap::new();
+    for m in graph.modules() {
+        let name = match names.get(&m.specifier) {
+            Some(name) => name,
+            None => continue,
+        };
+        let mut resolved = HashMap::new();
+        for (raw, dep) in &m.dependencies {
+            let specifier = match (&dep.maybe_type, &dep.maybe_code) {
+                (Resolved::Ok { specifier, .. }, _) | (_, Resolved::Ok { specifier, .. }) => {
+                    specifier
+                }
+                _ => continue,
+            };
+            // prefer the types of a javascript module if there are any, e.g. from
+            // the `X-TypeScript-Types` header
+            let specifier = match graph.get(specifier) {
+                Some(dep_module) => match &dep_module.maybe_types_dependency {
+                    Some((_, Resolved::Ok { specifier, .. })) => specifier,
+                    _ => &dep_module.specifier,
+                },
+                None => continue,
+            };
+            if let Some(dep_name) = names.get(specifier) {
+                resolved.insert(raw.as_str(), dep_name.clone());
+            }
+        }
+        resolutions.insert(name.clone(), resolved);
+    }
+
+    let roots = graph
+        .roots
+        .iter()
+        .filter_map(|(s, _)| graph.get(s).and_then(|m| names.get(&m.specifier)).cloned())
+        .collect();
+
+    let request = CheckRequest {
+        config: config.0,
+        roots,
+        files,
+        resolutions,
+        libs: options
+            .libs
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_ref()))
+            .collect(),
+    };
+
+    let mut runtime = match &options.tsc {
+        Tsc::Snapshot(snapshot) => JsRuntime::new(RuntimeOptions {
+            startup_snapshot: Some(Snapshot::Static(*snapshot)),
+            ..Default::default()
+        }),
+        Tsc::Source(typescript) => {
+            let mut runtime = JsRuntime::new(Default::default());
+            runtime.execute_script("deno:typescript.js", typescript)?;
+            runtime.execute_script("deno:check.js", CHECK_JS)?;
+            runtime
+        }
+    };
+    let value = runtime.execute_script(
+        "deno:type_check",
+        &format!(
+            "globalThis.__bundlerTypeCheck({})",
+            serde_json::to_string(&request)?
+        ),
+    )?;
+    let scope = &mut runtime.handle_scope();
+    let value = v8::Local::new(scope, value);
+    let mut diagnostics: Vec<TypeDiagnostic> = serde_v8::from_v8(scope, value)?;
+
+    // map the file names back to the specifiers
+    let specifiers: HashMap<&String, &ModuleSpecifier> =
+        names.iter().map(|(k, v)| (v, k)).collect();
+    for d in diagnostics.iter_mut() {
+        if let Some(specifier) = d.file_name.as_ref().and_then(|n| specifiers.get(n)) {
+            d.file_name = Some(specifier.to_string());
+        }
+    }
+    Ok(TypeDiagnostics(diagnostics))
+}
+
+fn tsc_name(specifier: &ModuleSpecifier, media_type: MediaType) -> String {
+    let ext = media_type.as_ts_extension();
+    if specifier.path().ends_with(ext) {
+        specifier.to_string()
+    } else {
+        format!("{}{}", specifier, ext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deno_core::resolve_url;
+
+    #[test]
+    fn tsc_name_should_have_the_right_extension() {
+        let m = resolve_url("https://example.com/mod.ts").unwrap();
+        assert_eq!(tsc_name(&m, MediaType::TypeScript), m.as_str());
+        let m = resolve_url("https://esm.sh/react").unwrap();
+        assert_eq!(
+            tsc_name(&m, MediaType::JavaScript),
+            "https://esm.sh/react.js"
+        );
+        let m = resolve_url("file:///tmp/types.d.ts").unwrap();
+        assert_eq!(tsc_name(&m, MediaType::Dts), m.as_str());
+    }
+
+    #[test]
+    fn type_diagnostics_should_display() {
+        let diagnostics = TypeDiagnostics(vec![TypeDiagnostic {
+            code: 2322,
+            category: DiagnosticCategory::Error,
+            message: "Type 'string' is not assignable to type 'number'.".into(),
+            file_name: Some("file:///tmp/main.ts".into()),
+            start: Some(Position {
+                line: 1,
+                character: 6,
+            }),
+            end: None,
+        }]);
+        assert!(diagnostics.has_errors());
+        assert_eq!(
+            diagnostics.to_string(),
+            "TS2322 [error]: Type 'string' is not assignable to type 'number'.\n    at file:///tmp/main.ts:2:7"
+        );
+    }
+}
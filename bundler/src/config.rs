@@ -1,8 +1,80 @@
 use deno_core::{
+    anyhow::{anyhow, bail, Context},
     error::AnyError,
     serde_json::{self, json, Value},
+    ModuleSpecifier,
 };
 use serde::{Deserialize, Serialize, Serializer};
+use std::{fmt, path::Path};
+
+/// The compiler options which are respected when loaded from a config file,
+/// the rest are ignored. The value is the expected type of the option.
+const ALLOWED_COMPILER_OPTIONS: &[(&str, OptionType)] = &[
+    ("allowJs", OptionType::Bool),
+    ("allowUnreachableCode", OptionType::Bool),
+    ("allowUnusedLabels", OptionType::Bool),
+    ("checkJs", OptionType::Bool),
+    ("emitDecoratorMetadata", OptionType::Bool),
+    ("exactOptionalPropertyTypes", OptionType::Bool),
+    ("experimentalDecorators", OptionType::Bool),
+    ("importsNotUsedAsValues", OptionType::String),
+    ("inlineSourceMap", OptionType::Bool),
+    ("inlineSources", OptionType::Bool),
+    ("jsx", OptionType::String),
+    ("jsxFactory", OptionType::String),
+    ("jsxFragmentFactory", OptionType::String),
+    ("jsxImportSource", OptionType::String),
+    ("keyofStringsOnly", OptionType::Bool),
+    ("lib", OptionType::StringArray),
+    ("noFallthroughCasesInSwitch", OptionType::Bool),
+    ("noImplicitAny", OptionType::Bool),
+    ("noImplicitOverride", OptionType::Bool),
+    ("noImplicitReturns", OptionType::Bool),
+    ("noImplicitThis", OptionType::Bool),
+    ("noImplicitUseStrict", OptionType::Bool),
+    ("noPropertyAccessFromIndexSignature", OptionType::Bool),
+    ("noStrictGenericChecks", OptionType::Bool),
+    ("noUncheckedIndexedAccess", OptionType::Bool),
+    ("noUnusedLocals", OptionType::Bool),
+    ("noUnusedParameters", OptionType::Bool),
+    ("sourceMap", OptionType::Bool),
+    ("strict", OptionType::Bool),
+    ("strictBindCallApply", OptionType::Bool),
+    ("strictFunctionTypes", OptionType::Bool),
+    ("strictNullChecks", OptionType::Bool),
+    ("strictPropertyInitialization", OptionType::Bool),
+    ("suppressExcessPropertyErrors", OptionType::Bool),
+    ("suppressImplicitAnyIndexErrors", OptionType::Bool),
+    ("useUnknownInCatchVariables", OptionType::Bool),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum OptionType {
+    Bool,
+    String,
+    StringArray,
+}
+
+impl OptionType {
+    fn check(&self, value: &Value) -> bool {
+        match self {
+            OptionType::Bool => value.is_boolean(),
+            OptionType::String => value.is_string(),
+            OptionType::StringArray => value
+                .as_array()
+                .map(|v| v.iter().all(|v| v.is_string()))
+                .unwrap_or(false),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            OptionType::Bool => "boolean",
+            OptionType::String => "string",
+            OptionType::StringArray => "array of strings",
+        }
+    }
+}
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -35,21 +107,29 @@ impl TsConfig {
     }
 }
 
-impl From<TsConfig> for deno_ast::EmitOptions {
-    fn from(config: TsConfig) -> Self {
-        let options: EmitConfigOptions = serde_json::from_value(config.0).unwrap();
+impl TryFrom<TsConfig> for deno_ast::EmitOptions {
+    type Error = AnyError;
+
+    fn try_from(config: TsConfig) -> Result<Self, Self::Error> {
+        let options: EmitConfigOptions =
+            serde_json::from_value(config.0).context("Invalid compiler options")?;
         let imports_not_used_as_values = match options.imports_not_used_as_values.as_str() {
+            "remove" => deno_ast::ImportsNotUsedAsValues::Remove,
             "preserve" => deno_ast::ImportsNotUsedAsValues::Preserve,
             "error" => deno_ast::ImportsNotUsedAsValues::Error,
-            _ => deno_ast::ImportsNotUsedAsValues::Remove,
+            v => bail!(
+                "Invalid value \"{}\" of compiler option \"importsNotUsedAsValues\".",
+                v
+            ),
         };
         let (transform_jsx, jsx_automatic, jsx_development) = match options.jsx.as_str() {
             "react" => (true, false, false),
             "react-jsx" => (true, true, false),
             "react-jsxdev" => (true, true, true),
-            _ => (false, false, false),
+            "preserve" => (false, false, false),
+            v => bail!("Invalid value \"{}\" of compiler option \"jsx\".", v),
         };
-        deno_ast::EmitOptions {
+        Ok(deno_ast::EmitOptions {
             emit_metadata: options.emit_decorator_metadata,
             imports_not_used_as_values,
             inline_source_map: options.inline_source_map,
@@ -62,14 +142,119 @@ impl From<TsConfig> for deno_ast::EmitOptions {
             jsx_import_source: options.jsx_import_source,
             transform_jsx,
             var_decl_imports: false,
+        })
+    }
+}
+
+/// The compiler options of a config file which were ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoredCompilerOptions {
+    pub items: Vec<String>,
+    pub maybe_specifier: Option<ModuleSpecifier>,
+}
+
+impl fmt::Display for IgnoredCompilerOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(specifier) = &self.maybe_specifier {
+            write!(
+                f,
+                "Unsupported compiler options in \"{}\".\n  The following options were ignored:\n    {}",
+                specifier,
+                self.items.join(", ")
+            )
+        } else {
+            write!(
+                f,
+                "Unsupported compiler options provided.\n  The following options were ignored:\n    {}",
+                self.items.join(", ")
+            )
         }
     }
 }
 
+/// A `deno.json` or `tsconfig.json` config file, which may contain comments and
+/// trailing commas (JSONC).
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    pub json: Value,
+    pub maybe_specifier: Option<ModuleSpecifier>,
+}
+
+impl ConfigFile {
+    /// Parse the text of a config file.
+    pub fn new(text: &str, maybe_specifier: Option<ModuleSpecifier>) -> Result<Self, AnyError> {
+        let name = maybe_specifier
+            .as_ref()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "config file".to_string());
+        let json = match jsonc_parser::parse_to_serde_value(text)
+            .map_err(|e| anyhow!("Unable to parse {}: {}", name, e))?
+        {
+            Some(json @ Value::Object(_)) => json,
+            Some(_) => bail!("{} should contain an object.", name),
+            None => json!({}),
+        };
+        Ok(Self {
+            json,
+            maybe_specifier,
+        })
+    }
+
+    /// Read and parse a config file.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, AnyError> {
+        let path = path.as_ref();
+        let path = std::fs::canonicalize(path)
+            .with_context(|| format!("Unable to find config file {}", path.display()))?;
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read config file {}", path.display()))?;
+        let specifier = ModuleSpecifier::from_file_path(&path)
+            .map_err(|_| anyhow!("Invalid config file path {}", path.display()))?;
+        Self::new(&text, Some(specifier))
+    }
+
+    /// Get the supported `compilerOptions` of the config file and the ignored
+    /// ones. Supported options with a value of the wrong type are errors.
+    pub fn to_compiler_options(&self) -> Result<(Value, Option<IgnoredCompilerOptions>), AnyError> {
+        let options = match self.json.get("compilerOptions") {
+            Some(Value::Object(options)) => options,
+            Some(_) => bail!("\"compilerOptions\" should be an object."),
+            None => return Ok((json!({}), None)),
+        };
+        let mut allowed = serde_json::Map::new();
+        let mut items = Vec::new();
+        for (k, v) in options {
+            match ALLOWED_COMPILER_OPTIONS
+                .iter()
+                .find(|(name, _)| *name == k.as_str())
+            {
+                Some((_, ty)) if ty.check(v) => {
+                    allowed.insert(k.clone(), v.clone());
+                }
+                Some((_, ty)) => bail!(
+                    "Invalid value of compiler option \"{}\": expected a {}, got {}.",
+                    k,
+                    ty.name(),
+                    v
+                ),
+                None => items.push(k.clone()),
+            }
+        }
+        let maybe_ignored = if items.is_empty() {
+            None
+        } else {
+            items.sort();
+            Some(IgnoredCompilerOptions {
+                items,
+                maybe_specifier: self.maybe_specifier.clone(),
+            })
+        };
+        Ok((Value::Object(allowed), maybe_ignored))
+    }
+}
+
 /// Represents the "default" type library that should be used when type
 /// checking the code in the module graph.  Note that a user provided config
 /// of `"lib"` would override this value.
-#[allow(dead_code)]
 #[derive(Debug, Clone, Eq, Hash, PartialEq)]
 pub enum TypeLib {
     DenoWindow,
@@ -104,7 +289,6 @@ impl Serialize for TypeLib {
 }
 
 /// An enum that represents the base tsc configuration to return.
-#[allow(dead_code)]
 pub enum ConfigType {
     /// Return a configuration for bundling, using swc to emit the bundle. This is
     /// independent of type checking.
@@ -121,6 +305,23 @@ pub enum ConfigType {
 /// For a given configuration type and optionally a configuration file, return a
 /// tuple of the resulting `TsConfig` struct and optionally any user
 /// configuration options that were ignored.
+pub fn get_ts_config_with_file(
+    config_type: ConfigType,
+    maybe_config_file: Option<&ConfigFile>,
+) -> Result<(TsConfig, Option<IgnoredCompilerOptions>), AnyError> {
+    let mut ts_config = get_ts_config(config_type)?;
+    let maybe_ignored = match maybe_config_file {
+        Some(config_file) => {
+            let (options, maybe_ignored) = config_file.to_compiler_options()?;
+            ts_config.merge(&options);
+            maybe_ignored
+        }
+        None => None,
+    };
+    Ok((ts_config, maybe_ignored))
+}
+
+/// Return the base configuration of the configuration type.
 pub fn get_ts_config(config_type: ConfigType) -> Result<TsConfig, AnyError> {
     let ts_config = match config_type {
         ConfigType::Bundle => TsConfig::new(json!({
@@ -235,3 +436,48 @@ fn json_merge(a: &mut Value, b: &Value) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_file_should_merge_over_base() {
+        let text = r#"{
+          // comments and trailing commas are allowed
+          "compilerOptions": {
+            "jsx": "react-jsx",
+            "jsxImportSource": "preact",
+            "strict": false,
+            "outDir": "dist",
+            "target": "es5",
+          },
+        }"#;
+        let config_file = ConfigFile::new(text, None).unwrap();
+        let (ts_config, maybe_ignored) =
+            get_ts_config_with_file(ConfigType::Bundle, Some(&config_file)).unwrap();
+        assert_eq!(ts_config.0["jsx"], "react-jsx");
+        assert_eq!(ts_config.0["jsxFactory"], "React.createElement");
+        assert_eq!(
+            maybe_ignored.unwrap().items,
+            vec!["outDir".to_string(), "target".to_string()]
+        );
+        let emit_options: deno_ast::EmitOptions = ts_config.try_into().unwrap();
+        assert!(emit_options.jsx_automatic);
+        assert_eq!(emit_options.jsx_import_source.as_deref(), Some("preact"));
+    }
+
+    #[test]
+    fn invalid_config_should_fail() {
+        assert!(ConfigFile::new("[]", None).is_err());
+        assert!(ConfigFile::new("{ \"compilerOptions\": ", None).is_err());
+        let config_file =
+            ConfigFile::new(r#"{ "compilerOptions": { "strict": "yes" } }"#, None).unwrap();
+        assert!(config_file.to_compiler_options().is_err());
+        let config_file =
+            ConfigFile::new(r#"{ "compilerOptions": { "jsx": "vue" } }"#, None).unwrap();
+        let (ts_config, _) =
+            get_ts_config_with_file(ConfigType::Bundle, Some(&config_file)).unwrap();
+        assert!(deno_ast::EmitOptions::try_from(ts_config).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+use deno_ast::swc::{
+    ast::{
+        AssignExpr, BindingIdent, ClassDecl, ClassExpr, ComputedPropName, Expr, ExprStmt, FnDecl,
+        FnExpr, Id, Ident, ImportDefaultSpecifier, ImportNamedSpecifier, ImportStarAsSpecifier,
+        KeyValueProp, Lit, MemberExpr, MemberProp, MetaPropKind, Module, ParenExpr, Prop, PropName,
+        Stmt, UpdateExpr,
+    },
+    common::{BytePos, Span, DUMMY_SP},
+    parser::{lexer::Lexer, Parser, StringInput, Syntax},
+    visit::{Visit, VisitMut, VisitMutWith, VisitWith},
+};
+use deno_core::{anyhow::anyhow, error::AnyError};
+use std::collections::{HashMap, HashSet};
+
+/// Compile time substitutions of global expressions, e.g. replace
+/// `process.env.NODE_ENV` with `"production"`.
+#[derive(Debug, Clone, Default)]
+pub struct Defines(Vec<(Vec<String>, Box<Expr>)>);
+
+impl Defines {
+    /// Parse the defines. Keys are identifiers or dotted member expressions, and
+    /// values are javascript expressions, so a string value must be quoted:
+    /// `{ "process.env.NODE_ENV": "\"production\"", "__DEV__": "false" }`.
+    pub fn new(define: &HashMap<String, String>) -> Result<Self, AnyError> {
+        let mut entries = define
+            .iter()
+            .map(|(k, v)| {
+                let path: Vec<String> = k.split('.').map(|s| s.trim().to_string()).collect();
+                if path.iter().any(|s| !is_ident(s)) {
+                    return Err(anyhow!("Invalid define key \"{}\".", k));
+                }
+                Ok((
+                    path,
+                    parse_expr(v).map_err(|e| {
+                        anyhow!("Invalid define value \"{}\" for \"{}\": {}", v, k, e)
+                    })?,
+                ))
+            })
+            .collect::<Result<Vec<_>, AnyError>>()?;
+        // longer paths first so that `a.b.c` wins over `a.b`
+        entries.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        Ok(Self(entries))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Substitute the defined expressions in the module. The module must have
+    /// been resolved by the swc resolver: an expression whose root identifier
+    /// refers to a binding in its scope is skipped, as it's not the global.
+    /// The bindings are told apart by their syntax contexts.
+    pub fn apply(&self, module: &mut Module) {
+        if self.is_empty() {
+            return;
+        }
+        let mut declared = DeclaredIdents::default();
+        module.visit_with(&mut declared);
+        let mut replacer = Replacer {
+            defines: self,
+            declared: declared.0,
+        };
+        module.visit_mut_with(&mut replacer);
+    }
+}
+
+struct Replacer<'a> {
+    defines: &'a Defines,
+    declared: HashSet<Id>,
+}
+
+impl Replacer<'_> {
+    fn find(&self, expr: &Expr) -> Option<&Expr> {
+        let mut path = Vec::new();
+        if !expr_path(expr, &mut path) {
+            return None;
+        }
+        if let Some(ident) = root_ident(expr) {
+            if self.declared.contains(&ident.to_id()) {
+                return None;
+            }
+        }
+        self.defines
+            .0
+            .iter()
+            .find(|(p, _)| p.len() == path.len() && p.iter().zip(path.iter()).all(|(a, b)| a == b))
+            .map(|(_, v)| &**v)
+    }
+}
+
+impl VisitMut for Replacer<'_> {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        if let Some(value) = self.find(expr) {
+            *expr = value.clone();
+            return;
+        }
+        expr.visit_mut_children_with(self);
+    }
+
+    // `{ __DEV__ }` is `{ __DEV__: __DEV__ }`
+    fn visit_mut_prop(&mut self, prop: &mut Prop) {
+        if let Prop::Shorthand(ident) = prop {
+            if let Some(value) = self.find(&Expr::Ident(ident.clone())) {
+                *prop = Prop::KeyValue(KeyValueProp {
+                    key: PropName::Ident(ident.clone()),
+                    value: Box::new(value.clone()),
+                });
+            }
+            return;
+        }
+        prop.visit_mut_children_with(self);
+    }
+
+    // never replace the target of an assignment or update
+    fn visit_mut_assign_expr(&mut self, expr: &mut AssignExpr) {
+        expr.right.visit_mut_with(self);
+    }
+
+    fn visit_mut_update_expr(&mut self, _expr: &mut UpdateExpr) {}
+}
+
+/// Get the dotted path of an identifier or a member expression chain.
+fn expr_path<'a>(expr: &'a Expr, path: &mut Vec<&'a str>) -> bool {
+    match expr {
+        Expr::Ident(ident) => {
+            path.push(&*ident.sym);
+            true
+        }
+        Expr::MetaProp(meta) if meta.kind == MetaPropKind::ImportMeta => {
+            path.push("import");
+            path.push("meta");
+            true
+        }
+        Expr::Member(MemberExpr { obj, prop, .. }) => {
+            if !expr_path(obj, path) {
+                return false;
+            }
+            match prop {
+                MemberProp::Ident(ident) => path.push(&*ident.sym),
+                MemberProp::Computed(ComputedPropName { expr, .. }) => match &**expr {
+                    Expr::Lit(Lit::Str(s)) => path.push(&*s.value),
+                    _ => return false,
+                },
+                _ => return false,
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The identifier at the root of a member expression chain.
+fn root_ident(expr: &Expr) -> Option<&Ident> {
+    match expr {
+        Expr::Ident(ident) => Some(ident),
+        Expr::Member(MemberExpr { obj, .. }) => root_ident(obj),
+        _ => None,
+    }
+}
+
+/// Collect the bindings declared in a module, a reference to a binding has the
+/// name and the syntax context of its declaration after resolving.
+#[derive(Default)]
+struct DeclaredIdents(HashSet<Id>);
+
+impl Visit for DeclaredIdents {
+    fn visit_binding_ident(&mut self, ident: &BindingIdent) {
+        self.0.insert(ident.id.to_id());
+    }
+
+    fn visit_fn_decl(&mut self, decl: &FnDecl) {
+        self.0.insert(decl.ident.to_id());
+        decl.visit_children_with(self);
+    }
+
+    fn visit_fn_expr(&mut self, expr: &FnExpr) {
+        if let Some(ident) = expr.ident.as_ref() {
+            self.0.insert(ident.to_id());
+        }
+        expr.visit_children_with(self);
+    }
+
+    fn visit_class_decl(&mut self, decl: &ClassDecl) {
+        self.0.insert(decl.ident.to_id());
+        decl.visit_children_with(self);
+    }
+
+    fn visit_class_expr(&mut self, expr: &ClassExpr) {
+        if let Some(ident) = expr.ident.as_ref() {
+            self.0.insert(ident.to_id());
+        }
+        expr.visit_children_with(self);
+    }
+
+    fn visit_import_named_specifier(&mut self, s: &ImportNamedSpecifier) {
+        self.0.insert(s.local.to_id());
+    }
+
+    fn visit_import_default_specifier(&mut self, s: &ImportDefaultSpecifier) {
+        self.0.insert(s.local.to_id());
+    }
+
+    fn visit_import_star_as_specifier(&mut self, s: &ImportStarAsSpecifier) {
+        self.0.insert(s.local.to_id());
+    }
+}
+
+pub(crate) fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {
+            chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        }
+        _ => false,
+    }
+}
+
+/// Parse a javascript expression, spans of the expression are dropped.
+pub(crate) fn parse_expr(source: &str) -> Result<Box<Expr>, String> {
+    // parse the value as `(value);` so that trailing tokens are rejected
+    let source = format!("({});", source);
+    let input = StringInput::new(&source, BytePos(1), BytePos(1 + source.len() as u32));
+    let lexer = Lexer::new(
+        Syntax::Es(Default::default()),
+        deno_ast::ES_VERSION,
+        input,
+        None,
+    );
+    let mut parser = Parser::new_from(lexer);
+    let script = parser
+        .parse_script()
+        .map_err(|e| format!("{:?}", e.kind()))?;
+    if let Some(e) = parser.take_errors().into_iter().next() {
+        return Err(format!("{:?}", e.kind()));
+    }
+    let mut expr = match <[Stmt; 1]>::try_from(script.body) {
+        Ok([Stmt::Expr(ExprStmt { expr, .. })]) => match *expr {
+            Expr::Paren(ParenExpr { expr, .. }) => expr,
+            _ => return Err("not an expression".into()),
+        },
+        _ => return Err("not an expression".into()),
+    };
+    // the expression is parsed out of the source map of the bundle, so its
+    // spans are meaningless
+    expr.visit_mut_with(&mut DropSpan);
+    Ok(expr)
+}
+
+struct DropSpan;
+
+impl VisitMut for DropSpan {
+    fn visit_mut_span(&mut self, span: &mut Span) {
+        *span = DUMMY_SP;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::emit_module;
+    use deno_ast::swc::{
+        common::{sync::Lrc, FileName, Globals, Mark, SourceMap, GLOBALS},
+        transforms::resolver,
+    };
+
+    fn transform(code: &str, define: &[(&str, &str)]) -> String {
+        let define = define
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let defines = Defines::new(&define).unwrap();
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Anon, code.to_string());
+        let lexer = Lexer::new(
+            Syntax::Es(Default::default()),
+            deno_ast::ES_VERSION,
+            StringInput::from(&*fm),
+            None,
+        );
+        let mut module = Parser::new_from(lexer).parse_module().unwrap();
+        GLOBALS.set(&Globals::new(), || {
+            module.visit_mut_with(&mut resolver(Mark::new(), Mark::new(), false));
+            defines.apply(&mut module);
+        });
+        emit_module(cm, &module, true).unwrap()
+    }
+
+    #[test]
+    fn defines_should_replace_global_expressions() {
+        let define = [
+            ("process.env.NODE_ENV", "\"production\""),
+            ("__DEV__", "false"),
+        ];
+        let code = transform(
+            "if (process.env.NODE_ENV !== 'production' && __DEV__) { debug(process.env.DEBUG); }",
+            &define,
+        );
+        assert!(!code.contains("NODE_ENV"));
+        assert!(!code.contains("__DEV__"));
+        assert!(code.contains("process.env.DEBUG"));
+    }
+
+    #[test]
+    fn defines_should_skip_local_bindings_and_assignments() {
+        let define = [
+            ("__DEV__", "false"),
+            ("process.env.NODE_ENV", "\"production\""),
+        ];
+        let code = transform(
+            "function f(__DEV__) { return __DEV__; } process.env.NODE_ENV = 'test';",
+            &define,
+        );
+        assert!(code.contains("return __DEV__"));
+        assert!(code.contains("process.env.NODE_ENV="));
+    }
+
+    #[test]
+    fn defines_should_check_the_scope_of_each_reference() {
+        let define = [
+            ("__DEV__", "false"),
+            ("process.env.NODE_ENV", "\"production\""),
+        ];
+        let code = transform(
+            r#"function f() { const process = { env: {} }; return process.env.NODE_ENV; }
+function g(__DEV__) { return __DEV__; }
+export const a = [__DEV__, process.env.NODE_ENV];"#,
+            &define,
+        );
+        assert!(code.contains("return process.env.NODE_ENV"));
+        assert!(code.contains("return __DEV__"));
+        assert!(code.contains(r#"a=[false,"production"]"#));
+    }
+
+    #[test]
+    fn defines_should_replace_shorthand_props() {
+        let code = transform(
+            "const __DEV__x = 1; export const o = { __DEV__, __DEV__x };",
+            &[("__DEV__", "false")],
+        );
+        assert!(code.contains("__DEV__:false"));
+        assert!(code.contains("__DEV__x}"));
+    }
+
+    #[test]
+    fn invalid_defines_should_fail() {
+        let define = HashMap::from([("process.env-x".to_string(), "1".to_string())]);
+        assert!(Defines::new(&define).is_err());
+        let define = HashMap::from([("__DEV__".to_string(), "fal se".to_string())]);
+        assert!(Defines::new(&define).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use deno_core::{error::AnyError, futures::FutureExt, resolve_import, ModuleSpecifier};
+use deno_graph::{
+    source::{LoadFuture, LoadResponse, Loader, ResolveResponse, Resolver},
+    ModuleGraph,
+};
+use import_map::ImportMap;
+
+/// Externals are resolved to this scheme, so that they are never loaded.
+const EXTERNAL_SCHEME: &str = "external";
+
+/// Match the import specifiers which are kept as imports in the bundle. A
+/// pattern is either the exact specifier, e.g. `react`, or a prefix ending
+/// with `*`, e.g. `https://esm.sh/*`.
+pub fn is_external(externals: &[String], specifier: &str) -> bool {
+    externals
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => specifier.starts_with(prefix),
+            None => specifier == pattern,
+        })
+}
+
+/// Whether the module is an external resolved by [GraphResolver].
+pub fn is_external_module(specifier: &ModuleSpecifier) -> bool {
+    specifier.scheme() == EXTERNAL_SCHEME
+}
+
+/// All the import specifiers in the graph which are externals.
+pub fn get_external_specifiers(graph: &ModuleGraph, externals: &[String]) -> Vec<String> {
+    let mut specifiers: Vec<_> = graph
+        .modules()
+        .into_iter()
+        .flat_map(|m| m.dependencies.keys())
+        .filter(|s| is_external(externals, s))
+        .cloned()
+        .collect();
+    specifiers.sort();
+    specifiers.dedup();
+    specifiers
+}
+
+/// A graph resolver which resolves the imports with the import map, and
+/// resolves the externals to the `external:` scheme.
+#[derive(Debug)]
+pub struct GraphResolver<'a> {
+    pub maybe_import_map: Option<&'a ImportMap>,
+    pub externals: &'a [String],
+}
+
+impl Resolver for GraphResolver<'_> {
+    fn resolve(&self, specifier: &str, referrer: &ModuleSpecifier) -> ResolveResponse {
+        if is_external(self.externals, specifier) {
+            return match ModuleSpecifier::parse(&format!("{}:{}", EXTERNAL_SCHEME, specifier)) {
+                Ok(specifier) => ResolveResponse::Specifier(specifier),
+                Err(err) => ResolveResponse::Err(err.into()),
+            };
+        }
+        let result: Result<ModuleSpecifier, AnyError> = match self.maybe_import_map {
+            Some(import_map) => import_map
+                .resolve(specifier, referrer)
+                .map_err(|e| e.into()),
+            None => resolve_import(specifier, referrer.as_str()).map_err(|e| e.into()),
+        };
+        match result {
+            Ok(specifier) => ResolveResponse::Specifier(specifier),
+            Err(err) => ResolveResponse::Err(err),
+        }
+    }
+}
+
+/// A graph loader which returns the externals as external modules without
+/// loading them, and delegates everything else to the inner loader.
+pub struct ExternalLoader<'a>(pub &'a mut dyn Loader);
+
+impl Loader for ExternalLoader<'_> {
+    fn load(&mut self, specifier: &ModuleSpecifier, is_dynamic: bool) -> LoadFuture {
+        if specifier.scheme() == EXTERNAL_SCHEME {
+            let specifier = specifier.clone();
+            return async move { Ok(Some(LoadResponse::External { specifier })) }.boxed_local();
+        }
+        self.0.load(specifier, is_dynamic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_external_should_work() {
+        let externals = vec!["react".to_string(), "https://esm.sh/*".to_string()];
+        assert!(is_external(&externals, "react"));
+        assert!(!is_external(&externals, "react-dom"));
+        assert!(is_external(&externals, "https://esm.sh/preact@10"));
+        assert!(!is_external(&externals, "./esm.sh/preact"));
+    }
+}
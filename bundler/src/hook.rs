@@ -1,44 +1,128 @@
 use deno_ast::swc::{
+    ast,
     bundler::{Hook, ModuleRecord},
-    common::Span,
+    common::{FileName, Span},
 };
-use deno_core::error::AnyError;
+use deno_core::{anyhow::anyhow, error::AnyError, ModuleSpecifier};
+use std::collections::HashMap;
+
+use crate::define::{is_ident, parse_expr};
+
+/// How `import.meta` of the bundled modules is rewritten.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportMeta {
+    /// `url` is the original url of the module, and `main` is `false` for all
+    /// but the entry module
+    Rewrite,
+    /// keep `import.meta` as is, i.e. all the modules get the `url` and `main`
+    /// of the bundle itself
+    Preserve,
+    /// `url` of the modules under the directory of the entry module is
+    /// rewritten relative to the base url, e.g. `file:///src/lib/a.ts` with
+    /// the entry `file:///src/main.ts` becomes `https://cdn.com/app/lib/a.ts`
+    /// for the base url `https://cdn.com/app/`. Other urls are kept.
+    Relative(ModuleSpecifier),
+}
 
 /// This contains the logic for Deno to rewrite the `import.meta` when bundling.
-pub struct BundleHook;
+pub struct BundleHook {
+    import_meta: ImportMeta,
+    root: ModuleSpecifier,
+    props: Vec<(String, Box<ast::Expr>)>,
+}
+
+impl BundleHook {
+    /// Create the hook, the values of the extra `import.meta` props are
+    /// javascript expressions.
+    pub fn new(
+        import_meta: ImportMeta,
+        root: ModuleSpecifier,
+        props: &HashMap<String, String>,
+    ) -> Result<Self, AnyError> {
+        let mut props = props
+            .iter()
+            .map(|(k, v)| {
+                let expr = parse_expr(v).map_err(|e| {
+                    anyhow!("Invalid import.meta value \"{}\" for \"{}\": {}", v, k, e)
+                })?;
+                Ok((k.clone(), expr))
+            })
+            .collect::<Result<Vec<_>, AnyError>>()?;
+        props.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Self {
+            import_meta,
+            root,
+            props,
+        })
+    }
+
+    fn get_url(&self, file_name: &FileName) -> String {
+        if let (ImportMeta::Relative(base), FileName::Url(url)) = (&self.import_meta, file_name) {
+            if let Ok(dir) = self.root.join(".") {
+                if let Some(path) = url.as_str().strip_prefix(dir.as_str()) {
+                    if let Ok(url) = base.join(path) {
+                        return url.to_string();
+                    }
+                }
+            }
+        }
+        file_name.to_string()
+    }
+}
 
 impl Hook for BundleHook {
     fn get_import_meta_props(
         &self,
         span: Span,
         module_record: &ModuleRecord,
-    ) -> Result<Vec<deno_ast::swc::ast::KeyValueProp>, AnyError> {
-        use deno_ast::swc::ast;
+    ) -> Result<Vec<ast::KeyValueProp>, AnyError> {
+        let import_meta_prop = |name: &str| {
+            ast::Expr::Member(ast::MemberExpr {
+                span,
+                obj: Box::new(ast::Expr::MetaProp(ast::MetaPropExpr {
+                    span,
+                    kind: ast::MetaPropKind::ImportMeta,
+                })),
+                prop: ast::MemberProp::Ident(ast::Ident::new(name.into(), span)),
+            })
+        };
 
-        Ok(vec![
+        let url = match self.import_meta {
+            ImportMeta::Preserve => import_meta_prop("url"),
+            _ => ast::Expr::Lit(ast::Lit::Str(ast::Str {
+                span,
+                value: self.get_url(&module_record.file_name).into(),
+                raw: None,
+            })),
+        };
+        let main = if module_record.is_entry || self.import_meta == ImportMeta::Preserve {
+            import_meta_prop("main")
+        } else {
+            ast::Expr::Lit(ast::Lit::Bool(ast::Bool { span, value: false }))
+        };
+
+        let mut props = vec![
             ast::KeyValueProp {
                 key: ast::PropName::Ident(ast::Ident::new("url".into(), span)),
-                value: Box::new(ast::Expr::Lit(ast::Lit::Str(ast::Str {
-                    span,
-                    value: module_record.file_name.to_string().into(),
-                    raw: None,
-                }))),
+                value: Box::new(url),
             },
             ast::KeyValueProp {
                 key: ast::PropName::Ident(ast::Ident::new("main".into(), span)),
-                value: Box::new(if module_record.is_entry {
-                    ast::Expr::Member(ast::MemberExpr {
-                        span,
-                        obj: Box::new(ast::Expr::MetaProp(ast::MetaPropExpr {
-                            span,
-                            kind: ast::MetaPropKind::ImportMeta,
-                        })),
-                        prop: ast::MemberProp::Ident(ast::Ident::new("main".into(), span)),
-                    })
-                } else {
-                    ast::Expr::Lit(ast::Lit::Bool(ast::Bool { span, value: false }))
-                }),
+                value: Box::new(main),
+            },
+        ];
+        props.extend(self.props.iter().map(|(k, v)| ast::KeyValueProp {
+            key: if is_ident(k) {
+                ast::PropName::Ident(ast::Ident::new(k.as_str().into(), span))
+            } else {
+                ast::PropName::Str(ast::Str {
+                    span,
+                    value: k.as_str().into(),
+                    raw: None,
+                })
             },
-        ])
+            value: v.clone(),
+        }));
+        Ok(props)
     }
 }
@@ -1,27 +1,77 @@
+mod assets;
+mod cache;
+mod check;
 mod config;
+mod define;
+mod externals;
 mod hook;
 mod loader;
+mod metafile;
 mod minify;
 mod options;
 mod output;
 mod resolver;
+mod task;
+mod treeshake;
+mod validate;
+mod vfs;
+mod workers;
 
-use askama::Template;
-use config::TsConfig;
+use assets::AssetLoader;
+use cache::bundle_graph_cached;
+use check::type_check;
+use define::Defines;
 use deno_ast::swc::{
     self,
     bundler::Bundler,
-    common::{FileName, FilePathMapping, Globals, SourceMap, GLOBALS},
+    common::{
+        comments::SingleThreadedComments, FileName, FilePathMapping, Globals, SourceMap, GLOBALS,
+    },
 };
 use deno_core::{anyhow::Context, error::AnyError, ModuleSpecifier};
+use deno_graph::{source::Loader, ModuleGraph};
 use deno_utils::{ModuleStore, UniversalModuleLoader};
 use derive_builder::Builder;
+use externals::{get_external_specifiers, ExternalLoader, GraphResolver};
 use hook::BundleHook;
-use loader::BundleLoader;
+use import_map::ImportMap;
+use loader::{BundleLoader, LoaderStats};
 use minify::minify;
-use output::gen_code;
+use output::{content_hash, gen_code, Wrapper};
 use resolver::BundleResolver;
-use std::{collections::HashMap, rc::Rc, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    sync::Arc,
+};
+#[cfg(feature = "async")]
+use task::CancelOnDrop;
+use task::{CancellableLoader, Progress};
+use treeshake::get_dropped_exports;
+use validate::validate_graph;
+use workers::name_chunks;
+
+pub use assets::{AssetHandler, AssetMatcher, AssetRule, AssetSource, LoadBytesFuture};
+pub use cache::get_cache_key;
+pub use check::{
+    create_tsc_snapshot, DiagnosticCategory, Position, Tsc, TypeCheckOptions, TypeDiagnostic,
+    TypeDiagnostics,
+};
+pub use config::{
+    get_ts_config, get_ts_config_with_file, ConfigFile, ConfigType, IgnoredCompilerOptions,
+    TsConfig, TypeLib,
+};
+pub use hook::ImportMeta;
+pub use metafile::{
+    ImportKind, Metafile, MetafileImport, MetafileInput, MetafileOutput, MetafileOutputInput,
+};
+pub use task::{BundleCancelled, BundleProgress, CancellationToken};
+pub use treeshake::{SideEffects, SideEffectsRule};
+pub use validate::{UnresolvedImport, UnresolvedImports};
+pub use vfs::VirtualFs;
+pub use workers::WorkerEntry;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BundleType {
@@ -43,54 +93,285 @@ pub struct BundleOptions {
     pub emit_ignore_directives: bool,
     pub module_store: Option<Arc<dyn ModuleStore>>,
     pub minify: bool,
+    /// generate a metafile which describes the inputs and the output of the bundle
+    pub metafile: bool,
+    /// side effects annotations for modules, later rules take precedence. The
+    /// `sideEffects` field of the `package.json` files of the graph is read
+    /// while loading the graph, and these rules take precedence over it.
+    pub side_effects: Vec<SideEffectsRule>,
+    /// rules to import non-javascript assets, the first matched rule is used.
+    /// By default `.wasm` imports are loaded with [AssetHandler::Wasm].
+    pub loaders: Vec<AssetRule>,
+    /// global expressions substituted at compile time, e.g.
+    /// `process.env.NODE_ENV` => `"production"`. Values are javascript
+    /// expressions, so strings need to be quoted.
+    pub define: HashMap<String, String>,
+    /// text put at the very beginning of the bundle, e.g. a license header
+    pub banner: Option<String>,
+    /// text put at the very end of the bundle
+    pub footer: Option<String>,
+    /// whether to put `'use strict';` at the beginning of the bundled code
+    pub use_strict: bool,
+    /// a custom wrapper template which replaces the default one. The bundled
+    /// code is put where the `{{ body }}` placeholder is.
+    pub template: Option<String>,
+    /// type check the module graph with the typescript compiler before
+    /// bundling, the bundling fails with [TypeDiagnostics] on type errors
+    pub type_check: Option<TypeCheckOptions>,
+    /// compute a hash of the bundled code, see [BundleOutput::hashed_file_name]
+    pub content_hash: bool,
+    /// how `import.meta` of the bundled modules is rewritten
+    pub import_meta: ImportMeta,
+    /// extra `import.meta` props, e.g. `env` => `{ "MODE": "production" }`.
+    /// Values are javascript expressions like `define`.
+    pub import_meta_props: HashMap<String, String>,
+    /// cache the outputs keyed by the sources of the graph and the options, so
+    /// that bundling unchanged inputs returns the cached output. Failing to
+    /// write the cache is logged and doesn't fail the bundling.
+    pub bundle_cache: Option<Arc<dyn ModuleStore>>,
+    /// imports which are kept as is instead of being bundled, either the exact
+    /// specifier, e.g. `react`, or a prefix ending with `*`, e.g.
+    /// `https://esm.sh/*`
+    pub externals: Vec<String>,
+    /// import map used to resolve the imports
+    pub import_map: Option<Arc<ImportMap>>,
 }
 
-#[derive(Template)]
-#[template(path = "layout.j2", escape = "none")]
-struct BundledJs {
-    body: String,
-    bundle_type: BundleType,
+/// The result of [bundle].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleOutput {
+    pub code: String,
+    pub maybe_map: Option<String>,
+    pub maybe_metafile: Option<Metafile>,
+    /// exports of each module which are not imported by any other module, nor
+    /// used by the module itself, and have been removed from the bundle by tree
+    /// shaking. Only reported with `minify`, as the dead code is removed by the
+    /// minifier.
+    pub dropped_exports: BTreeMap<String, Vec<String>>,
+    /// hex encoded sha256 of the code, only if `content_hash` is set
+    pub maybe_hash: Option<String>,
+    /// workers created by the code keyed by the file names of their chunks
+    pub workers: BTreeMap<String, WorkerEntry>,
+    /// the bundled workers keyed by their file names, which are named by the
+    /// hash of their code and should be put next to the bundle. The chunks of
+    /// nested workers are here as well.
+    pub chunks: BTreeMap<String, BundleOutput>,
 }
 
-/// Given a module graph, generate and return a bundle of the graph and
-/// optionally its source map. Unlike emitting with `check_and_maybe_emit` and
-/// `emit`, which store the emitted modules in the cache, this function simply
-/// returns the output.
+/// Given a root module, generate and return a bundle of its module graph and
+/// optionally its source map and metafile. Unlike emitting with
+/// `check_and_maybe_emit` and `emit`, which store the emitted modules in the
+/// cache, this function simply returns the output.
 pub async fn bundle(
-    // graph: &ModuleGraph,
     root: ModuleSpecifier,
     options: BundleOptions,
-) -> Result<(String, Option<String>), AnyError> {
-    let mut loader = UniversalModuleLoader::new(options.module_store, false);
-    let graph_owned = deno_graph::create_graph(
+) -> Result<BundleOutput, AnyError> {
+    let mut loader = UniversalModuleLoader::new(options.module_store.clone(), false);
+    let mut loader = AssetLoader::new(&mut loader, &options.loaders);
+    bundle_with_workers(root, &options, &mut loader, &Progress::default()).await
+}
+
+/// Same as [bundle], but the returned future is `Send`, so that it could be
+/// spawned onto a multi-threaded runtime. The bundling runs on the blocking
+/// pool of tokio, it stops with [BundleCancelled] once the token is cancelled
+/// or the future is dropped, and `on_progress` is called as it goes. Requires
+/// the `async` feature.
+#[cfg(feature = "async")]
+pub async fn bundle_async(
+    root: ModuleSpecifier,
+    options: BundleOptions,
+    token: CancellationToken,
+    on_progress: impl Fn(BundleProgress) + Send + Sync + 'static,
+) -> Result<BundleOutput, AnyError> {
+    // the blocking task can't be aborted, stop it once the future is dropped,
+    // without cancelling the other bundles sharing the token
+    let token = token.child_token();
+    let guard = CancelOnDrop::new(token.clone());
+    let progress = Progress::new(token, on_progress);
+    let result = tokio::task::spawn_blocking(move || -> Result<BundleOutput, AnyError> {
+        // the graph loader and swc are not `Send`, so the whole bundling runs
+        // on a single thread of the blocking pool
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(async {
+            let mut loader = UniversalModuleLoader::new(options.module_store.clone(), false);
+            let mut loader = AssetLoader::new(&mut loader, &options.loaders);
+            bundle_with_workers(root, &options, &mut loader, &progress).await
+        })
+    })
+    .await;
+    guard.disarm();
+    result?
+}
+
+/// Same as [bundle], but all the modules, including the assets, are loaded by
+/// the given loader, e.g. a [VirtualFs], so that nothing is read from the
+/// disk or the network. `options.module_store` is not used. The raw content of
+/// the assets is loaded with [AssetSource::load_bytes].
+pub async fn bundle_with_loader(
+    root: ModuleSpecifier,
+    options: BundleOptions,
+    loader: &mut dyn AssetSource,
+) -> Result<BundleOutput, AnyError> {
+    let mut loader = AssetLoader::new(loader, &options.loaders);
+    bundle_with_workers(root, &options, &mut loader, &Progress::default()).await
+}
+
+/// Bundle the root, then bundle each worker created by the code as a separate
+/// chunk.
+async fn bundle_with_workers(
+    root: ModuleSpecifier,
+    options: &BundleOptions,
+    loader: &mut dyn Loader,
+    progress: &Progress,
+) -> Result<BundleOutput, AnyError> {
+    let mut loader = CancellableLoader {
+        inner: loader,
+        token: progress.token(),
+    };
+    let graph = create_graph_with_progress(root, &mut loader, options, progress).await?;
+    let root_options = with_package_side_effects(&graph, &mut loader, options, progress).await?;
+    let mut output = bundle_graph_cached(&graph, &root_options, progress).await?;
+
+    let mut pending: Vec<_> = output.workers.clone().into_iter().collect();
+    while let Some((name, worker)) = pending.pop() {
+        if output.chunks.contains_key(&name) {
+            continue;
+        }
+        let specifier = ModuleSpecifier::parse(&worker.specifier)?;
+        let options = BundleOptions {
+            bundle_type: if worker.module {
+                BundleType::Module
+            } else {
+                BundleType::Classic
+            },
+            ..options.clone()
+        };
+        let graph = create_graph_with_progress(specifier, &mut loader, &options, progress).await?;
+        let options = with_package_side_effects(&graph, &mut loader, &options, progress).await?;
+        let chunk = bundle_graph_cached(&graph, &options, progress).await?;
+        pending.extend(chunk.workers.clone());
+        output.chunks.insert(name, chunk);
+    }
+    name_chunks(&mut output);
+    Ok(output)
+}
+
+async fn create_graph_with_progress(
+    root: ModuleSpecifier,
+    loader: &mut dyn Loader,
+    options: &BundleOptions,
+    progress: &Progress,
+) -> Result<ModuleGraph, AnyError> {
+    let graph = create_graph(root.clone(), loader, options).await;
+    progress.check()?;
+    progress.report(BundleProgress::GraphBuilt {
+        root,
+        modules: graph.modules().len(),
+    });
+    Ok(graph)
+}
+
+/// Put the rules from the `sideEffects` field of the `package.json` files of
+/// the graph before the rules of the options, so that the latter win.
+async fn with_package_side_effects(
+    graph: &ModuleGraph,
+    loader: &mut dyn Loader,
+    options: &BundleOptions,
+    progress: &Progress,
+) -> Result<BundleOptions, AnyError> {
+    let mut side_effects = SideEffectsRule::from_graph(graph, loader).await;
+    progress.check()?;
+    if side_effects.is_empty() {
+        return Ok(options.clone());
+    }
+    side_effects.extend(options.side_effects.iter().cloned());
+    Ok(BundleOptions {
+        side_effects,
+        ..options.clone()
+    })
+}
+
+async fn create_graph(
+    root: ModuleSpecifier,
+    loader: &mut dyn Loader,
+    options: &BundleOptions,
+) -> ModuleGraph {
+    let resolver = GraphResolver {
+        maybe_import_map: options.import_map.as_deref(),
+        externals: &options.externals,
+    };
+    let mut loader = ExternalLoader(loader);
+    deno_graph::create_graph(
         vec![(root, deno_graph::ModuleKind::Esm)],
         false,
         None,
         &mut loader,
-        None,
+        Some(&resolver),
         None,
         None,
         None,
     )
-    .await;
-    let graph = &graph_owned;
+    .await
+}
+
+pub(crate) fn bundle_graph(
+    graph: &ModuleGraph,
+    options: &BundleOptions,
+    progress: &Progress,
+) -> Result<BundleOutput, AnyError> {
+    validate_graph(graph)?;
+    if let Some(check_options) = &options.type_check {
+        let diagnostics = type_check(graph, check_options)?;
+        if diagnostics.has_errors() {
+            return Err(diagnostics.into());
+        }
+        progress.check()?;
+    }
 
+    let defines = Defines::new(&options.define)?;
+    // This hook will rewrite the `import.meta` when bundling to give a consistent
+    // behavior between bundled and unbundled code.
+    let hook = Box::new(BundleHook::new(
+        options.import_meta.clone(),
+        graph.roots[0].0.clone(),
+        &options.import_meta_props,
+    )?);
     let globals = Globals::new();
     GLOBALS.set(&globals, || {
-        let emit_options: deno_ast::EmitOptions = options.ts_config.into();
+        let emit_options: deno_ast::EmitOptions = options.ts_config.clone().try_into()?;
 
         let cm = Rc::new(SourceMap::new(FilePathMapping::empty()));
-        let loader = BundleLoader::new(cm.clone(), &emit_options, graph);
+        // comments are shared by all the modules so that the minifier could
+        // honor annotations like `/*#__PURE__*/`
+        let comments = SingleThreadedComments::default();
+        let stats = RefCell::new(LoaderStats::default());
+        let loader = BundleLoader::new(
+            cm.clone(),
+            comments.clone(),
+            &emit_options,
+            graph,
+            options,
+            &defines,
+            &stats,
+        );
+        loader.transpile_all(|done, total| {
+            progress.check()?;
+            progress.report(BundleProgress::Transpiled { done, total });
+            Ok(())
+        })?;
         let resolver = BundleResolver(graph);
         let config = swc::bundler::Config {
             module: options.bundle_type.into(),
             disable_fixer: options.minify,
             disable_hygiene: options.minify,
+            external_modules: get_external_specifiers(graph, &options.externals)
+                .into_iter()
+                .map(|s| s.into())
+                .collect(),
             ..Default::default()
         };
-        // This hook will rewrite the `import.meta` when bundling to give a consistent
-        // behavior between bundled and unbundled code.
-        let hook = Box::new(BundleHook);
         let mut bundler = Bundler::new(&globals, cm.clone(), loader, resolver, config, hook);
         let mut entries = HashMap::new();
         entries.insert(
@@ -101,24 +382,56 @@ pub async fn bundle(
             .bundle(entries)
             .context("Unable to output during bundling.")?;
 
+        progress.check()?;
         if options.minify {
-            modules = minify(cm.clone(), modules);
+            progress.report(BundleProgress::Minifying);
+            modules = minify(cm.clone(), &comments, modules);
+            progress.check()?;
         }
 
-        let (mut code, may_map) = gen_code(
-            cm,
+        progress.report(BundleProgress::Emitting);
+        let stats = stats.into_inner();
+        let wrapper = Wrapper::new(options, !stats.workers.is_empty())?;
+        let (code, may_map) = gen_code(
+            cm.clone(),
             &modules[0],
             &emit_options,
             options.emit_ignore_directives,
             options.minify,
+            &wrapper,
         )?;
 
-        let tpl = BundledJs {
-            body: code,
-            bundle_type: options.bundle_type,
+        let maybe_metafile = if options.metafile {
+            let mut metafile = Metafile::new(graph, &stats.transpiled_sizes);
+            metafile.add_output(
+                "bundle",
+                &graph.roots[0].0,
+                cm,
+                &modules[0].module,
+                code.len(),
+                options.minify,
+            )?;
+            Some(metafile)
+        } else {
+            None
+        };
+
+        let maybe_hash = options.content_hash.then(|| content_hash(&code));
+        let dropped_exports = if options.minify {
+            get_dropped_exports(graph, &stats.module_infos, &modules[0].module)
+        } else {
+            BTreeMap::new()
         };
-        code = tpl.render()?;
-        Ok((code, may_map))
+
+        Ok(BundleOutput {
+            code,
+            maybe_map: may_map,
+            maybe_metafile,
+            dropped_exports,
+            maybe_hash,
+            workers: stats.workers,
+            chunks: BTreeMap::new(),
+        })
     })
 }
 
@@ -126,7 +439,7 @@ pub async fn bundle(
 mod tests {
     use std::path::Path;
 
-    use deno_core::resolve_url_or_path;
+    use deno_core::{resolve_url_or_path, serde_json};
 
     use super::*;
 
@@ -149,7 +462,7 @@ mod tests {
         let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/01_main.ts");
         let f = f.to_string_lossy().to_string();
         let m = resolve_url_or_path(&f).unwrap();
-        let (_bundle, _) = bundle(m.clone(), options.clone()).await.unwrap();
+        let _output = bundle(m.clone(), options.clone()).await.unwrap();
         let store = options.module_store.unwrap();
         let ret = store.get(m.as_str()).await;
         assert!(ret.is_ok());
@@ -172,4 +485,558 @@ mod tests {
         let ret = bundle(m.clone(), options.clone()).await;
         assert!(ret.is_ok());
     }
+
+    #[tokio::test]
+    async fn bundle_with_metafile_should_work() {
+        let options = BundleOptions {
+            metafile: true,
+            ..BundleOptions::default()
+        };
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/02_global.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let output = bundle(m.clone(), options.clone()).await.unwrap();
+        let metafile = output.maybe_metafile.unwrap();
+        assert_eq!(metafile.inputs.len(), 2);
+        let base = m.join("base.ts").unwrap();
+        assert_eq!(metafile.inputs[m.as_str()].imports[0].path, base.as_str());
+        let output = &metafile.outputs["bundle"];
+        assert!(output.inputs.contains_key(base.as_str()));
+        assert!(metafile.render_tree().contains(base.as_str()));
+    }
+
+    #[tokio::test]
+    async fn bundle_should_report_dropped_exports() {
+        let options = BundleOptions {
+            side_effects: vec![SideEffectsRule::new("**/unused.ts", SideEffects::Free)],
+            ..BundleOptions::default()
+        };
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/04_tree_shaking.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let output = bundle(m.clone(), options).await.unwrap();
+        let math = m.join("math.ts").unwrap();
+        assert_eq!(
+            output.dropped_exports[math.as_str()],
+            vec!["sub".to_string()]
+        );
+        assert!(!output.code.contains("unused module"));
+
+        // nothing is tree shaken without the minifier
+        let options = BundleOptions {
+            side_effects: vec![SideEffectsRule::new("**/unused.ts", SideEffects::Free)],
+            minify: false,
+            ..BundleOptions::default()
+        };
+        let output = bundle(m.clone(), options).await.unwrap();
+        assert!(output.dropped_exports.is_empty());
+        assert!(output.code.contains("sub"));
+    }
+
+    #[tokio::test]
+    async fn bundle_with_assets_should_work() {
+        let options = BundleOptions {
+            loaders: vec![
+                AssetRule::extension("css", AssetHandler::Css),
+                AssetRule::extension("svg", AssetHandler::Text),
+            ],
+            minify: false,
+            ..BundleOptions::default()
+        };
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/05_assets.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let output = bundle(m.clone(), options).await.unwrap();
+        assert!(output.code.contains("document.createElement"));
+        assert!(output.code.contains("<svg"));
+    }
+
+    #[tokio::test]
+    async fn bundle_with_define_should_remove_dead_branches() {
+        let options = BundleOptions {
+            define: HashMap::from([
+                ("process.env.NODE_ENV".into(), "\"production\"".into()),
+                ("__DEV__".into(), "false".into()),
+            ]),
+            ..BundleOptions::default()
+        };
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/06_define.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let output = bundle(m.clone(), options).await.unwrap();
+        assert!(!output.code.contains("development build"));
+        assert!(!output.code.contains("debug tools"));
+        assert!(output.code.contains("production build"));
+    }
+
+    #[tokio::test]
+    async fn bundle_with_wasm_should_work() {
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/07_wasm.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let output = bundle(m.clone(), BundleOptions::default()).await.unwrap();
+        assert!(output.code.contains("WebAssembly.compile"));
+        assert!(output.code.contains("WebAssembly.instantiate"));
+    }
+
+    #[tokio::test]
+    async fn bundle_should_store_the_assets() {
+        let dir = tempfile::tempdir().unwrap();
+        let store: Arc<dyn ModuleStore> = Arc::new(deno_utils::FsModuleStore::new(dir.path()));
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/07_wasm.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let options = BundleOptions {
+            module_store: Some(store.clone()),
+            ..BundleOptions::default()
+        };
+        bundle(m.clone(), options).await.unwrap();
+        let wasm = m.join("add.wasm").unwrap();
+        let bytes = store.get(wasm.as_str()).await.unwrap();
+        let expected = include_bytes!("../fixtures/add.wasm");
+        assert_eq!(&*bytes, &expected[..]);
+    }
+
+    #[tokio::test]
+    async fn bundle_with_import_meta_options_should_work() {
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/08_import_meta.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let options = BundleOptions {
+            import_meta: ImportMeta::Relative(
+                resolve_url_or_path("https://cdn.example.com/app/").unwrap(),
+            ),
+            import_meta_props: HashMap::from([(
+                "env".into(),
+                r#"{ "MODE": "production" }"#.into(),
+            )]),
+            minify: false,
+            ..BundleOptions::default()
+        };
+        let output = bundle(m.clone(), options).await.unwrap();
+        assert!(output.code.contains("https://cdn.example.com/app/meta.ts"));
+        assert!(output.code.contains("production"));
+
+        let options = BundleOptions {
+            import_meta: ImportMeta::Preserve,
+            minify: false,
+            ..BundleOptions::default()
+        };
+        let output = bundle(m.clone(), options).await.unwrap();
+        assert!(!output.code.contains("meta.ts"));
+        assert!(output.code.contains("import.meta.url"));
+    }
+
+    #[tokio::test]
+    async fn bundle_with_virtual_fs_should_work() {
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert(
+                "file:///app/main.ts",
+                "import { greet } from './lib/greet.ts';\nimport msg from './msg.txt';\nconsole.log(greet(msg));",
+            )
+            .unwrap();
+        fs.insert(
+            "file:///app/lib/greet.ts",
+            "export function greet(name: string): string { return `hello ${name}`; }",
+        )
+        .unwrap();
+        fs.insert("file:///app/msg.txt", "virtual world").unwrap();
+        let options = BundleOptions {
+            module_store: None,
+            loaders: vec![AssetRule::extension("txt", AssetHandler::Text)],
+            minify: false,
+            ..BundleOptions::default()
+        };
+        let output = bundle_with_loader(m, options, &mut fs).await.unwrap();
+        assert!(output.code.contains("hello"));
+        assert!(output.code.contains("virtual world"));
+    }
+
+    #[tokio::test]
+    async fn bundle_with_virtual_fs_should_load_binary_assets() {
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert(
+                "file:///app/main.ts",
+                "import wasm from './add.wasm';\nconsole.log(wasm);",
+            )
+            .unwrap();
+        // not valid utf-8
+        fs.insert_bytes("file:///app/add.wasm", &b"\0asm\x01\0\0\0\x80\xff"[..])
+            .unwrap();
+        let options = BundleOptions {
+            module_store: None,
+            minify: false,
+            ..BundleOptions::default()
+        };
+        let output = bundle_with_loader(m, options, &mut fs).await.unwrap();
+        assert!(output.code.contains(r#"atob("AGFzbQEAAACA/w==")"#));
+    }
+
+    #[cfg(feature = "tsc")]
+    #[tokio::test]
+    async fn bundle_should_fail_on_type_errors() {
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert(
+                "file:///app/main.ts",
+                "import { add } from './add.ts';\nconst sum: number = add(1, 2);\nexport const s: string = sum;",
+            )
+            .unwrap();
+        fs.insert(
+            "file:///app/add.ts",
+            "export function add(a: number, b: number): number { return a + b; }",
+        )
+        .unwrap();
+        let options = BundleOptions {
+            module_store: None,
+            type_check: Some(TypeCheckOptions::embedded(TypeLib::DenoWindow)),
+            ..BundleOptions::default()
+        };
+        let err = bundle_with_loader(m, options, &mut fs).await.unwrap_err();
+        let diagnostics = err.downcast::<TypeDiagnostics>().unwrap();
+        assert_eq!(diagnostics.0.len(), 1);
+        let d = &diagnostics.0[0];
+        assert_eq!(d.code, 2322);
+        assert_eq!(d.file_name.as_deref(), Some("file:///app/main.ts"));
+        assert_eq!(
+            d.start,
+            Some(Position {
+                line: 2,
+                character: 13
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn bundle_should_be_deterministic() {
+        let options = BundleOptions {
+            content_hash: true,
+            ..BundleOptions::default()
+        };
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/04_tree_shaking.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let output1 = bundle(m.clone(), options.clone()).await.unwrap();
+        let output2 = bundle(m.clone(), options).await.unwrap();
+        assert_eq!(output1.code, output2.code);
+        assert!(output1.maybe_hash.is_some());
+        assert_eq!(output1.maybe_hash, output2.maybe_hash);
+    }
+
+    #[tokio::test]
+    async fn bundle_with_externals_and_import_map_should_work() {
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert(
+                "file:///app/main.ts",
+                "import React from 'react';\nimport { greet } from 'lib/greet.ts';\nconsole.log(React, greet());",
+            )
+            .unwrap();
+        fs.insert(
+            "file:///app/vendor/greet.ts",
+            "export function greet() { return 'hello'; }",
+        )
+        .unwrap();
+        let import_map =
+            import_map::parse_from_json(&m, r#"{ "imports": { "lib/": "./vendor/" } }"#)
+                .unwrap()
+                .import_map;
+        let options = BundleOptions {
+            module_store: None,
+            externals: vec!["react".into()],
+            import_map: Some(Arc::new(import_map)),
+            minify: false,
+            ..BundleOptions::default()
+        };
+        let output = bundle_with_loader(m, options, &mut fs).await.unwrap();
+        assert!(output.code.contains("from \"react\""));
+        assert!(output.code.contains("hello"));
+    }
+
+    #[tokio::test]
+    async fn bundle_with_cache_should_work() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache: Arc<dyn ModuleStore> = Arc::new(deno_utils::FsModuleStore::new(dir.path()));
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert("file:///app/main.ts", "console.log('cached bundle');")
+            .unwrap();
+        let options = BundleOptions {
+            module_store: None,
+            bundle_cache: Some(cache.clone()),
+            ..BundleOptions::default()
+        };
+        let output = bundle_with_loader(m.clone(), options.clone(), &mut fs)
+            .await
+            .unwrap();
+
+        let graph = create_graph(m.clone(), &mut fs, &options).await;
+        let key = get_cache_key(&graph, &options).unwrap();
+        let cached: BundleOutput = serde_json::from_slice(&cache.get(&key).await.unwrap()).unwrap();
+        assert_eq!(cached.code, output.code);
+
+        // a rebuild would not return the planted output
+        let planted = BundleOutput {
+            code: "planted".into(),
+            ..cached
+        };
+        cache
+            .put(key.clone(), &serde_json::to_vec(&planted).unwrap())
+            .await
+            .unwrap();
+        let output2 = bundle_with_loader(m.clone(), options.clone(), &mut fs)
+            .await
+            .unwrap();
+        assert_eq!(output2.code, "planted");
+
+        // a different option is a different key
+        let options = BundleOptions {
+            banner: Some("// banner".into()),
+            ..options
+        };
+        assert_ne!(get_cache_key(&graph, &options).unwrap(), key);
+        let output3 = bundle_with_loader(m, options, &mut fs).await.unwrap();
+        assert!(output3.code.contains("cached bundle"));
+    }
+
+    #[tokio::test]
+    async fn bundle_should_read_side_effects_of_packages() {
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert(
+                "file:///app/main.ts",
+                "import { a } from './node_modules/lib/index.js';\nconsole.log('main');",
+            )
+            .unwrap();
+        fs.insert(
+            "file:///app/node_modules/lib/index.js",
+            "console.log('lib side effect');\nexport const a = 1;",
+        )
+        .unwrap();
+        fs.insert(
+            "file:///app/node_modules/lib/package.json",
+            r#"{ "sideEffects": false }"#,
+        )
+        .unwrap();
+        let output = bundle_with_loader(m.clone(), BundleOptions::default(), &mut fs)
+            .await
+            .unwrap();
+        assert!(output.code.contains("main"));
+        assert!(!output.code.contains("lib side effect"));
+
+        // the rules of the options win
+        let options = BundleOptions {
+            side_effects: vec![SideEffectsRule::new("**/lib/**", SideEffects::Keep)],
+            ..BundleOptions::default()
+        };
+        let output = bundle_with_loader(m, options, &mut fs).await.unwrap();
+        assert!(output.code.contains("lib side effect"));
+    }
+
+    #[tokio::test]
+    async fn bundle_should_map_the_code_after_the_banner() {
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert("file:///app/main.ts", "console.log('mapped');")
+            .unwrap();
+        let mut ts_config = get_ts_config(ConfigType::Bundle).unwrap();
+        ts_config.merge(&serde_json::json!({ "sourceMap": true }));
+        let options = BundleOptions {
+            bundle_type: BundleType::Module,
+            ts_config,
+            banner: Some("/*! a */\n/*! b */".into()),
+            footer: Some("// end".into()),
+            minify: false,
+            ..BundleOptions::default()
+        };
+        let output = bundle_with_loader(m.clone(), options.clone(), &mut fs)
+            .await
+            .unwrap();
+        let line = output
+            .code
+            .lines()
+            .position(|l| l.contains("mapped"))
+            .unwrap();
+        assert!(line > 2);
+        let map: serde_json::Value = serde_json::from_str(&output.maybe_map.unwrap()).unwrap();
+        let mappings = map["mappings"].as_str().unwrap();
+        // every line before the generated code is a `;` without mappings
+        assert_eq!(mappings.chars().take_while(|c| *c == ';').count(), line);
+
+        let mut ts_config = get_ts_config(ConfigType::Bundle).unwrap();
+        ts_config.merge(&serde_json::json!({ "inlineSourceMap": true }));
+        let options = BundleOptions {
+            ts_config,
+            ..options
+        };
+        let output = bundle_with_loader(m, options, &mut fs).await.unwrap();
+        let last = output.code.trim_end().lines().last().unwrap();
+        assert!(last.starts_with("//# sourceMappingURL=data:application/json;base64,"));
+        assert!(output.code.contains("// end\n//# sourceMappingURL="));
+    }
+
+    #[tokio::test]
+    async fn bundle_should_not_fail_if_the_cache_fails() {
+        // the cache can't create its entries under a file
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let cache: Arc<dyn ModuleStore> = Arc::new(deno_utils::FsModuleStore::new(file.path()));
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert("file:///app/main.ts", "console.log('uncached bundle');")
+            .unwrap();
+        let options = BundleOptions {
+            module_store: None,
+            bundle_cache: Some(cache),
+            ..BundleOptions::default()
+        };
+        let output = bundle_with_loader(m, options, &mut fs).await.unwrap();
+        assert!(output.code.contains("uncached bundle"));
+    }
+
+    #[tokio::test]
+    async fn bundle_should_report_unresolved_imports() {
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert(
+                "file:///app/main.ts",
+                "import { a } from './a.ts';\nimport { b } from './b.ts';\nconsole.log(a, b);",
+            )
+            .unwrap();
+        fs.insert("file:///app/a.ts", "export const a = 1;")
+            .unwrap();
+        let options = BundleOptions {
+            module_store: None,
+            ..BundleOptions::default()
+        };
+        let err = bundle_with_loader(m.clone(), options, &mut fs)
+            .await
+            .unwrap_err();
+        let unresolved = err.downcast::<UnresolvedImports>().unwrap();
+        assert_eq!(unresolved.0.len(), 1);
+        let item = &unresolved.0[0];
+        assert_eq!(item.specifier, "./b.ts");
+        assert_eq!(item.maybe_referrer.as_ref(), Some(&m));
+        assert_eq!(
+            item.maybe_position,
+            Some(Position {
+                line: 1,
+                character: 18
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn bundle_with_workers_should_work() {
+        let options = BundleOptions::default();
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/09_worker.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let output = bundle(m.clone(), options).await.unwrap();
+        assert_eq!(output.workers.len(), 1);
+        assert_eq!(output.chunks.len(), 1);
+        let (name, worker) = output.workers.iter().next().unwrap();
+        assert_eq!(worker.specifier, m.join("worker.ts").unwrap().as_str());
+        assert!(worker.module);
+        assert!(output.code.contains(&format!("./{}", name)));
+        assert!(output
+            .code
+            .contains("const __deno_bundle_url = import.meta.url;"));
+        let chunk = &output.chunks[name];
+        assert!(chunk.code.contains("postMessage"));
+        assert!(!chunk.code.contains("__deno_bundle_url"));
+        // the chunk is named by its content
+        assert_eq!(
+            name,
+            &format!("worker.{}.js", &content_hash(&chunk.code)[..8])
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bundle_async_should_report_progress() {
+        let options = BundleOptions {
+            minify: true,
+            ..BundleOptions::default()
+        };
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/02_global.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let events_cloned = events.clone();
+        let token = CancellationToken::new();
+        let fut = bundle_async(m.clone(), options, token.clone(), move |e| {
+            events_cloned.lock().unwrap().push(e)
+        });
+        // the future could be spawned onto the multi-threaded runtime
+        let output = tokio::spawn(fut).await.unwrap().unwrap();
+        assert!(!output.code.is_empty());
+        // the token could be reused
+        assert!(!token.is_cancelled());
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events[0],
+            BundleProgress::GraphBuilt {
+                root: m,
+                modules: 2
+            }
+        );
+        assert!(events.contains(&BundleProgress::Transpiled { done: 2, total: 2 }));
+        assert!(events.contains(&BundleProgress::Minifying));
+        assert_eq!(events.last(), Some(&BundleProgress::Emitting));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn bundle_async_should_be_cancellable() {
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/02_global.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = bundle_async(m, BundleOptions::default(), token, |_| {})
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<BundleCancelled>().is_some());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn bundle_async_should_be_cancelled_when_dropped() {
+        let f = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/02_global.ts");
+        let f = f.to_string_lossy().to_string();
+        let m = resolve_url_or_path(&f).unwrap();
+        let token = CancellationToken::new();
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (resume_tx, resume_rx) = std::sync::mpsc::channel::<()>();
+        let (started_tx, resume_rx) = (
+            std::sync::Mutex::new(started_tx),
+            std::sync::Mutex::new(resume_rx),
+        );
+        // hold the bundling at its first event until the future is dropped
+        let fut = bundle_async(m, BundleOptions::default(), token.clone(), move |_| {
+            let _ = started_tx.lock().unwrap().send(());
+            let _ = resume_rx.lock().unwrap().recv();
+        });
+        let handle = tokio::spawn(fut);
+        let started_rx = tokio::task::spawn_blocking(move || {
+            started_rx.recv().unwrap();
+            started_rx
+        })
+        .await
+        .unwrap();
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+        drop(resume_tx);
+        // the bundling stops without another event, the callback is dropped
+        // with it
+        let events = tokio::task::spawn_blocking(move || started_rx.iter().count())
+            .await
+            .unwrap();
+        assert_eq!(events, 0);
+        // the token of the caller could be shared with the other bundles
+        assert!(!token.is_cancelled());
+    }
 }
@@ -9,28 +9,115 @@ use deno_ast::{
 };
 
 use deno_core::{anyhow::anyhow, error::AnyError, ModuleSpecifier};
-use deno_graph::ModuleGraph;
-use std::rc::Rc;
+use deno_graph::{ModuleGraph, Resolved};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    rc::Rc,
+};
+
+use crate::{
+    define::Defines,
+    externals::is_external_module,
+    output::emit_module,
+    treeshake::{remove_unused_imports, ModuleInfo},
+    workers::{collect_workers, WorkerEntry},
+    BundleOptions,
+};
+
+/// Information collected while the modules are loaded.
+#[derive(Debug, Default)]
+pub struct LoaderStats {
+    /// size of each transpiled module, only collected when a metafile is requested
+    pub transpiled_sizes: HashMap<ModuleSpecifier, usize>,
+    pub module_infos: HashMap<ModuleSpecifier, ModuleInfo>,
+    /// workers created by the modules keyed by the file names of their chunks
+    pub workers: BTreeMap<String, WorkerEntry>,
+}
+
+type TranspiledModule = (Rc<swc::common::SourceFile>, swc::ast::Module);
 
 /// A module loader for swc which does the appropriate retrieval and transpiling
 /// of modules from the graph.
 pub struct BundleLoader<'a> {
     cm: Rc<swc::common::SourceMap>,
+    comments: SingleThreadedComments,
     emit_options: &'a deno_ast::EmitOptions,
     graph: &'a ModuleGraph,
+    options: &'a BundleOptions,
+    defines: &'a Defines,
+    stats: &'a RefCell<LoaderStats>,
+    transpiled: RefCell<HashMap<ModuleSpecifier, TranspiledModule>>,
 }
 
 impl<'a> BundleLoader<'a> {
     pub fn new(
         cm: Rc<swc::common::SourceMap>,
+        comments: SingleThreadedComments,
         emit_options: &'a deno_ast::EmitOptions,
         graph: &'a ModuleGraph,
+        options: &'a BundleOptions,
+        defines: &'a Defines,
+        stats: &'a RefCell<LoaderStats>,
     ) -> Self {
         Self {
             cm,
+            comments,
             emit_options,
             graph,
+            options,
+            defines,
+            stats,
+            transpiled: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Transpile all the modules reachable from the roots in the order of their
+    /// specifiers. Marks and source positions are allocated as the modules are
+    /// transpiled, so doing it upfront makes the output independent of the
+    /// order in which swc loads the modules. `on_transpiled` is called with the
+    /// number of the transpiled modules and the total after each module, and
+    /// an error from it stops the transpiling.
+    pub fn transpile_all(
+        &self,
+        on_transpiled: impl Fn(usize, usize) -> Result<(), AnyError>,
+    ) -> Result<(), AnyError> {
+        let mut specifiers = BTreeSet::new();
+        let mut pending: Vec<_> = self.graph.roots.iter().map(|(s, _)| s.clone()).collect();
+        while let Some(specifier) = pending.pop() {
+            let m = match self.graph.get(&specifier) {
+                Some(m) if !is_external_module(&m.specifier) => m,
+                _ => continue,
+            };
+            if !specifiers.insert(m.specifier.clone()) {
+                continue;
+            }
+            for dep in m.dependencies.values() {
+                if let Resolved::Ok { specifier, .. } = &dep.maybe_code {
+                    pending.push(specifier.clone());
+                }
+            }
+        }
+
+        let mut transpiled = self.transpiled.borrow_mut();
+        let total = specifiers.len();
+        for (i, specifier) in specifiers.into_iter().enumerate() {
+            let m = match self.graph.get(&specifier) {
+                Some(m) => m,
+                None => continue,
+            };
+            let (fm, module) = transpile_module(
+                &specifier,
+                m.maybe_source.as_ref().map(|s| s.as_ref()).unwrap_or(""),
+                m.media_type,
+                self.emit_options,
+                self.cm.clone(),
+                &self.comments,
+            )?;
+            transpiled.insert(specifier, (fm, module));
+            on_transpiled(i + 1, total)?;
         }
+        Ok(())
     }
 }
 
@@ -42,13 +129,34 @@ impl swc::bundler::Load for BundleLoader<'_> {
         match file_name {
             swc::common::FileName::Url(specifier) => {
                 if let Some(m) = self.graph.get(specifier) {
-                    let (fm, module) = transpile_module(
-                        specifier,
-                        m.maybe_source.as_ref().map(|s| s.as_ref()).unwrap_or(""),
-                        m.media_type,
-                        self.emit_options,
-                        self.cm.clone(),
-                    )?;
+                    let maybe_transpiled = self.transpiled.borrow_mut().remove(specifier);
+                    let (fm, mut module) = match maybe_transpiled {
+                        Some(transpiled) => transpiled,
+                        None => transpile_module(
+                            specifier,
+                            m.maybe_source.as_ref().map(|s| s.as_ref()).unwrap_or(""),
+                            m.media_type,
+                            self.emit_options,
+                            self.cm.clone(),
+                            &self.comments,
+                        )?,
+                    };
+                    // substitute the defines first, so that the dead branches
+                    // could be removed by tree shaking and minification
+                    self.defines.apply(&mut module);
+                    remove_unused_imports(&mut module, &self.options.side_effects, |s| {
+                        self.graph.resolve_dependency(s, specifier, false).cloned()
+                    });
+
+                    let mut stats = self.stats.borrow_mut();
+                    collect_workers(&mut module, specifier, &mut stats.workers);
+                    if self.options.metafile {
+                        let code = emit_module(self.cm.clone(), &module, false)?;
+                        stats.transpiled_sizes.insert(specifier.clone(), code.len());
+                    }
+                    stats
+                        .module_infos
+                        .insert(specifier.clone(), ModuleInfo::new(&module));
                     Ok(swc::bundler::ModuleData {
                         fm,
                         module,
@@ -61,10 +169,10 @@ impl swc::bundler::Load for BundleLoader<'_> {
                     ))
                 }
             }
-            _ => unreachable!(
+            _ => Err(anyhow!(
                 "Received a request for unsupported filename {:?}",
                 file_name
-            ),
+            )),
         }
     }
 }
@@ -76,6 +184,7 @@ fn transpile_module(
     media_type: MediaType,
     options: &deno_ast::EmitOptions,
     cm: Rc<swc::common::SourceMap>,
+    comments: &SingleThreadedComments,
 ) -> Result<(Rc<swc::common::SourceFile>, swc::ast::Module), AnyError> {
     let source = strip_bom(source);
     let source = if media_type == MediaType::Json {
@@ -88,13 +197,12 @@ fn transpile_module(
     };
     let source_file = cm.new_source_file(FileName::Url(specifier.clone()), source);
     let input = StringInput::from(&*source_file);
-    let comments = SingleThreadedComments::default();
     let syntax = if media_type == MediaType::Json {
         get_syntax(MediaType::JavaScript)
     } else {
         get_syntax(media_type)
     };
-    let lexer = Lexer::new(syntax, deno_ast::ES_VERSION, input, Some(&comments));
+    let lexer = Lexer::new(syntax, deno_ast::ES_VERSION, input, Some(comments));
     let mut parser = swc::parser::Parser::new_from(lexer);
     let module = parser
         .parse_module()
@@ -110,7 +218,7 @@ fn transpile_module(
         swc::ast::Program::Module(module),
         options,
         cm,
-        &comments,
+        comments,
         top_level_mark,
         &diagnostics,
     )?;
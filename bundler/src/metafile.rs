@@ -0,0 +1,317 @@
+use deno_ast::swc::{
+    ast::{Module, ModuleItem, Stmt},
+    common::{sync::Lrc, FileName, SourceMap, Spanned, DUMMY_SP},
+    visit::{Visit, VisitWith},
+};
+use deno_core::{error::AnyError, ModuleSpecifier};
+use deno_graph::{ModuleGraph, Resolved};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::output::emit_module;
+
+/// An esbuild style description of the inputs and outputs of a bundle. It
+/// could be serialized to JSON and fed to the usual bundle analyzers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metafile {
+    pub inputs: BTreeMap<String, MetafileInput>,
+    pub outputs: BTreeMap<String, MetafileOutput>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetafileInput {
+    /// size of the original source
+    pub bytes: usize,
+    /// size of the module after it is transpiled to javascript
+    pub bytes_transpiled: usize,
+    pub imports: Vec<MetafileImport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetafileImport {
+    pub path: String,
+    pub kind: ImportKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportKind {
+    ImportStatement,
+    DynamicImport,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetafileOutput {
+    pub bytes: usize,
+    pub entry_point: Option<String>,
+    pub inputs: BTreeMap<String, MetafileOutputInput>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetafileOutputInput {
+    pub bytes_in_output: usize,
+}
+
+impl Metafile {
+    /// Create the inputs part of the metafile from the module graph and the
+    /// transpiled size of each module.
+    pub(crate) fn new(graph: &ModuleGraph, transpiled: &HashMap<ModuleSpecifier, usize>) -> Self {
+        let inputs = graph
+            .modules()
+            .into_iter()
+            .map(|m| {
+                let imports = m
+                    .dependencies
+                    .values()
+                    .filter_map(|dep| match &dep.maybe_code {
+                        Resolved::Ok { specifier, .. } => Some(MetafileImport {
+                            path: specifier.to_string(),
+                            kind: if dep.is_dynamic {
+                                ImportKind::DynamicImport
+                            } else {
+                                ImportKind::ImportStatement
+                            },
+                        }),
+                        _ => None,
+                    })
+                    .collect();
+                let input = MetafileInput {
+                    bytes: m.maybe_source.as_ref().map(|s| s.len()).unwrap_or_default(),
+                    bytes_transpiled: transpiled.get(&m.specifier).copied().unwrap_or_default(),
+                    imports,
+                };
+                (m.specifier.to_string(), input)
+            })
+            .collect();
+        Self {
+            inputs,
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    /// Record an output of the bundle, attributing the emitted code of the
+    /// bundled module back to the input it came from.
+    pub(crate) fn add_output(
+        &mut self,
+        name: &str,
+        entry_point: &ModuleSpecifier,
+        cm: Lrc<SourceMap>,
+        module: &Module,
+        bytes: usize,
+        minify: bool,
+    ) -> Result<(), AnyError> {
+        let mut collector = OutputCollector {
+            cm,
+            minify,
+            inputs: BTreeMap::new(),
+            error: None,
+        };
+        module.visit_with(&mut collector);
+        if let Some(e) = collector.error {
+            return Err(e);
+        }
+        self.outputs.insert(
+            name.to_string(),
+            MetafileOutput {
+                bytes,
+                entry_point: Some(entry_point.to_string()),
+                inputs: collector.inputs,
+            },
+        );
+        Ok(())
+    }
+
+    /// Render the metafile as a dependency tree, starting from the entry point
+    /// of each output. Every line shows the bytes the module contributes to
+    /// the output and its share of the output.
+    pub fn render_tree(&self) -> String {
+        let mut s = String::new();
+        for (name, output) in &self.outputs {
+            writeln!(&mut s, "{} - {}", name, format_bytes(output.bytes)).unwrap();
+            if let Some(entry) = output.entry_point.as_ref() {
+                let mut seen = HashSet::new();
+                self.render_node(&mut s, output, entry, "", &mut seen);
+            }
+        }
+        s
+    }
+
+    fn render_node<'a>(
+        &'a self,
+        s: &mut String,
+        output: &MetafileOutput,
+        path: &'a str,
+        prefix: &str,
+        seen: &mut HashSet<&'a str>,
+    ) {
+        let in_output = output
+            .inputs
+            .get(path)
+            .map(|i| i.bytes_in_output)
+            .unwrap_or_default();
+        let share = if output.bytes > 0 {
+            in_output as f64 * 100.0 / output.bytes as f64
+        } else {
+            0.0
+        };
+        if !seen.insert(path) {
+            writeln!(s, "{}{} (*)", prefix, path).unwrap();
+            return;
+        }
+        writeln!(
+            s,
+            "{}{} - {} ({:.1}%)",
+            prefix,
+            path,
+            format_bytes(in_output),
+            share
+        )
+        .unwrap();
+
+        if let Some(input) = self.inputs.get(path) {
+            let child_prefix = format!("{}  ", prefix.replace("└─ ", "   ").replace("├─ ", "│  "));
+            let len = input.imports.len();
+            for (i, import) in input.imports.iter().enumerate() {
+                let branch = if i + 1 == len { "└─ " } else { "├─ " };
+                let prefix = format!("{}{}", child_prefix, branch);
+                self.render_node(s, output, &import.path, &prefix, seen);
+            }
+        }
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1}mb", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1}kb", bytes as f64 / 1024.0)
+    } else {
+        format!("{}b", bytes)
+    }
+}
+
+/// Walk the bundled module, emit every statement which still carries the span
+/// of its original source, and attribute the emitted size to that source.
+/// Statements created by the bundler itself (e.g. the iife wrapper) have a
+/// dummy span, so we look into their children instead.
+struct OutputCollector {
+    cm: Lrc<SourceMap>,
+    minify: bool,
+    inputs: BTreeMap<String, MetafileOutputInput>,
+    error: Option<AnyError>,
+}
+
+impl OutputCollector {
+    fn record(&mut self, item: ModuleItem) -> bool {
+        let span = item.span();
+        if span.is_dummy() {
+            return false;
+        }
+        let name = match self.cm.span_to_filename(span) {
+            FileName::Url(specifier) => specifier.to_string(),
+            _ => return false,
+        };
+        let module = Module {
+            span: DUMMY_SP,
+            body: vec![item],
+            shebang: None,
+        };
+        match emit_module(self.cm.clone(), &module, self.minify) {
+            Ok(code) => {
+                self.inputs.entry(name).or_default().bytes_in_output += code.len();
+            }
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        true
+    }
+}
+
+impl Visit for OutputCollector {
+    fn visit_module_item(&mut self, item: &ModuleItem) {
+        match item {
+            ModuleItem::Stmt(stmt) => self.visit_stmt(stmt),
+            _ => {
+                if !self.record(item.clone()) {
+                    item.visit_children_with(self);
+                }
+            }
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if !self.record(ModuleItem::Stmt(stmt.clone())) {
+            stmt.visit_children_with(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_tree_should_work() {
+        let mut metafile = Metafile::default();
+        metafile.inputs.insert(
+            "file:///main.ts".into(),
+            MetafileInput {
+                bytes: 200,
+                bytes_transpiled: 150,
+                imports: vec![
+                    MetafileImport {
+                        path: "file:///a.ts".into(),
+                        kind: ImportKind::ImportStatement,
+                    },
+                    MetafileImport {
+                        path: "file:///b.ts".into(),
+                        kind: ImportKind::ImportStatement,
+                    },
+                ],
+            },
+        );
+        metafile.inputs.insert(
+            "file:///a.ts".into(),
+            MetafileInput {
+                bytes: 100,
+                bytes_transpiled: 80,
+                imports: vec![MetafileImport {
+                    path: "file:///b.ts".into(),
+                    kind: ImportKind::ImportStatement,
+                }],
+            },
+        );
+        let mut output = MetafileOutput {
+            bytes: 200,
+            entry_point: Some("file:///main.ts".into()),
+            ..Default::default()
+        };
+        for (name, bytes) in [
+            ("file:///main.ts", 100),
+            ("file:///a.ts", 50),
+            ("file:///b.ts", 50),
+        ] {
+            output.inputs.insert(
+                name.into(),
+                MetafileOutputInput {
+                    bytes_in_output: bytes,
+                },
+            );
+        }
+        metafile.outputs.insert("bundle".into(), output);
+
+        let expected = "bundle - 200b\n\
+            file:///main.ts - 100b (50.0%)\n  \
+            ├─ file:///a.ts - 50b (25.0%)\n  \
+            │    └─ file:///b.ts - 50b (25.0%)\n  \
+            └─ file:///b.ts (*)\n";
+        assert_eq!(metafile.render_tree(), expected);
+    }
+}
@@ -1,7 +1,7 @@
 use deno_ast::swc;
 use swc::{
     bundler::Bundle,
-    common::{sync::Lrc, Mark, SourceMap},
+    common::{comments::SingleThreadedComments, sync::Lrc, Mark, SourceMap},
     transforms::fixer,
     visit::VisitMutWith,
 };
@@ -12,7 +12,11 @@ use swc_ecma_minifier::{
 
 const MINIFY_CONFIG: &str = include_str!("config.json");
 
-pub fn minify(cm: Lrc<SourceMap>, modules: Vec<Bundle>) -> Vec<Bundle> {
+pub fn minify(
+    cm: Lrc<SourceMap>,
+    comments: &SingleThreadedComments,
+    modules: Vec<Bundle>,
+) -> Vec<Bundle> {
     let options: MinifyOptions = serde_json::from_str(MINIFY_CONFIG).unwrap();
     modules
         .into_iter()
@@ -20,7 +24,7 @@ pub fn minify(cm: Lrc<SourceMap>, modules: Vec<Bundle>) -> Vec<Bundle> {
             b.module = optimize(
                 b.module.into(),
                 cm.clone(),
-                None,
+                Some(comments),
                 None,
                 &options,
                 &ExtraOptions {
@@ -1,11 +1,11 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use deno_ast::swc;
+use deno_ast::{swc, MediaType};
 use deno_utils::FsModuleStore;
 
 use crate::{
     config::{get_ts_config, ConfigType},
-    BundleOptions, BundleType,
+    AssetHandler, AssetRule, BundleOptions, BundleType, ImportMeta,
 };
 
 impl Default for BundleType {
@@ -32,6 +32,21 @@ impl Default for BundleOptions {
             emit_ignore_directives: false,
             module_store: Some(Arc::new(FsModuleStore::default())),
             minify: true,
+            metafile: false,
+            side_effects: vec![],
+            loaders: vec![AssetRule::media_type(MediaType::Wasm, AssetHandler::Wasm)],
+            define: HashMap::new(),
+            banner: None,
+            footer: None,
+            use_strict: true,
+            template: None,
+            type_check: None,
+            content_hash: false,
+            import_meta: ImportMeta::Rewrite,
+            import_meta_props: HashMap::new(),
+            bundle_cache: None,
+            externals: vec![],
+            import_map: None,
         }
     }
 }
@@ -1,8 +1,15 @@
+use askama::Template;
 use deno_ast::{
     swc::{self, bundler::Bundle, common::sync::Lrc, common::SourceMap},
     EmitOptions,
 };
-use deno_core::{anyhow::Context, error::AnyError};
+use deno_core::{anyhow::bail, anyhow::Context, error::AnyError};
+use sha2::{Digest, Sha256};
+
+use crate::{BundleOptions, BundleOutput, BundleType};
+
+/// number of hex characters of the content hash put into file names
+const FILE_NAME_HASH_LEN: usize = 8;
 
 const IGNORE_DIRECTIVES: &[&str] = &[
     "// deno-fmt-ignore-file",
@@ -11,12 +18,39 @@ const IGNORE_DIRECTIVES: &[&str] = &[
     "",
 ];
 
+/// The url of a module bundle.
+const MODULE_BUNDLE_URL: &str = "const __deno_bundle_url = import.meta.url;";
+
+/// The url of a classic script bundle, `document.currentScript` in the
+/// browsers, or `location` in the workers.
+const SCRIPT_BUNDLE_URL: &str =
+    "var __deno_bundle_url = globalThis.document?.currentScript?.src ?? globalThis.location?.href;";
+
+const BODY_PLACEHOLDERS: &[&str] = &["{{ body }}", "{{body}}"];
+
+/// Where the generated code goes when the wrapper is rendered.
+const BODY_MARKER: &str = "\u{0}deno_bundler_body\u{0}";
+
+#[derive(Template)]
+#[template(path = "layout.j2", escape = "none")]
+struct BundledJs {
+    body: String,
+    bundle_type: BundleType,
+    use_strict: bool,
+    /// the definition of the bundle url, empty if it's not needed
+    bundle_url: &'static str,
+}
+
+/// Generate the code of the bundle and put it into the wrapper. The source map
+/// is shifted by the code put before the generated code, and the inline
+/// `sourceMappingURL` comment is always the last line.
 pub fn gen_code(
     cm: Lrc<SourceMap>,
     bundle: &Bundle,
     emit_options: &EmitOptions,
     ignore_directive: bool,
     minify: bool,
+    wrapper: &Wrapper,
 ) -> Result<(String, Option<String>), AnyError> {
     let source_map_config = deno_ast::SourceMapConfig {
         inline_sources: emit_options.inline_sources,
@@ -52,14 +86,25 @@ pub fn gen_code(
             .emit_module(&bundle.module)
             .context("Unable to emit during bundling.")?;
     }
-    let mut code = String::from_utf8(buf).context("Emitted code is an invalid string.")?;
+    let body = String::from_utf8(buf).context("Emitted code is an invalid string.")?;
+    let mut code = wrapper.wrap(&body);
 
     let mut maybe_map: Option<String> = None;
     if emit_options.source_map || emit_options.inline_source_map {
+        let (line, col) = wrapper.body_start();
+        for (_, lc) in srcmap.iter_mut() {
+            if lc.line == 0 {
+                lc.col += col;
+            }
+            lc.line += line;
+        }
         let mut buf = Vec::new();
         cm.build_source_map_with_config(&mut srcmap, None, source_map_config)
             .to_writer(&mut buf)?;
         if emit_options.inline_source_map {
+            if !code.ends_with('\n') {
+                code.push('\n');
+            }
             let encoded_map = format!(
                 "//# sourceMappingURL=data:application/json;base64,{}\n",
                 base64::encode(buf)
@@ -72,3 +117,245 @@ pub fn gen_code(
 
     Ok((code, maybe_map))
 }
+
+/// Emit a single module without source map or directives. This is used to
+/// measure the size of modules and module items.
+pub fn emit_module(
+    cm: Lrc<SourceMap>,
+    module: &swc::ast::Module,
+    minify: bool,
+) -> Result<String, AnyError> {
+    let mut buf = Vec::new();
+    {
+        let cfg = swc::codegen::Config {
+            minify,
+            ..Default::default()
+        };
+        let wr = Box::new(swc::codegen::text_writer::JsWriter::new(
+            cm.clone(),
+            "\n",
+            &mut buf,
+            None,
+        ));
+        let mut emitter = swc::codegen::Emitter {
+            cfg,
+            cm,
+            comments: None,
+            wr,
+        };
+        emitter
+            .emit_module(module)
+            .context("Unable to emit module.")?;
+    }
+    String::from_utf8(buf).context("Emitted code is an invalid string.")
+}
+
+/// The code put around the generated code: the banner, the default or the
+/// custom template, and the footer.
+pub struct Wrapper {
+    prefix: String,
+    suffix: String,
+    footer: Option<String>,
+}
+
+impl Wrapper {
+    /// The url of the bundle is defined for the worker chunks if the code
+    /// creates workers.
+    pub fn new(options: &BundleOptions, has_workers: bool) -> Result<Self, AnyError> {
+        let bundle_url = match (has_workers, options.bundle_type) {
+            (false, _) => "",
+            (true, BundleType::Classic) => SCRIPT_BUNDLE_URL,
+            (true, _) => MODULE_BUNDLE_URL,
+        };
+        let rendered = match options.template.as_ref() {
+            Some(tpl) if has_workers => {
+                render_template(tpl, &format!("{}\n{}", bundle_url, BODY_MARKER))?
+            }
+            Some(tpl) => render_template(tpl, BODY_MARKER)?,
+            None => BundledJs {
+                body: BODY_MARKER.to_string(),
+                bundle_type: options.bundle_type,
+                use_strict: options.use_strict,
+                bundle_url,
+            }
+            .render()?,
+        };
+        let (prefix, suffix) = rendered
+            .split_once(BODY_MARKER)
+            .context("The template lost the bundled code.")?;
+        let prefix = match options.banner.as_ref() {
+            Some(banner) => format!("{}\n{}", banner.trim_end_matches('\n'), prefix),
+            None => prefix.to_string(),
+        };
+        Ok(Self {
+            prefix,
+            suffix: suffix.to_string(),
+            footer: options.footer.clone(),
+        })
+    }
+
+    pub fn wrap(&self, body: &str) -> String {
+        let mut code = format!("{}{}{}", self.prefix, body, self.suffix);
+        if let Some(footer) = self.footer.as_ref() {
+            if !code.ends_with('\n') {
+                code.push('\n');
+            }
+            code.push_str(footer);
+        }
+        code
+    }
+
+    /// The zero based line and column (in UTF-16 code units, as the source
+    /// maps count them) where the generated code starts.
+    fn body_start(&self) -> (u32, u32) {
+        let line = self.prefix.matches('\n').count();
+        let last_line = self.prefix.rsplit('\n').next().unwrap_or_default();
+        (line as u32, last_line.encode_utf16().count() as u32)
+    }
+}
+
+pub fn content_hash(code: &str) -> String {
+    format!("{:x}", Sha256::digest(code.as_bytes()))
+}
+
+impl BundleOutput {
+    /// Put the content hash before the extension of the file name, e.g.
+    /// `app.js` => `app.3f9a1c2b.js`. The name is returned as is if there's no
+    /// hash.
+    pub fn hashed_file_name(&self, name: &str) -> String {
+        let hash = match &self.maybe_hash {
+            Some(hash) => &hash[..FILE_NAME_HASH_LEN.min(hash.len())],
+            None => return name.to_string(),
+        };
+        match name.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() && !ext.contains('/') => {
+                format!("{}.{}.{}", stem, hash, ext)
+            }
+            _ => format!("{}.{}", name, hash),
+        }
+    }
+}
+
+fn render_template(tpl: &str, body: &str) -> Result<String, AnyError> {
+    match BODY_PLACEHOLDERS.iter().find(|p| tpl.contains(*p)) {
+        Some(placeholder) => Ok(tpl.replacen(placeholder, body, 1)),
+        None => bail!(
+            "Custom template must have a {} placeholder for the bundled code.",
+            BODY_PLACEHOLDERS[0]
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap_code(
+        body: &str,
+        options: &BundleOptions,
+        has_workers: bool,
+    ) -> Result<String, AnyError> {
+        Ok(Wrapper::new(options, has_workers)?.wrap(body))
+    }
+
+    #[test]
+    fn wrap_code_should_add_banner_and_footer() {
+        let options = BundleOptions {
+            banner: Some("/*! MIT License */\n".into()),
+            footer: Some("// end".into()),
+            use_strict: false,
+            ..BundleOptions::default()
+        };
+        let code = wrap_code("console.log(1);", &options, false).unwrap();
+        assert!(code.starts_with("/*! MIT License */\n"));
+        assert!(code.ends_with("\n// end"));
+        assert!(!code.contains("use strict"));
+    }
+
+    #[test]
+    fn wrapper_should_tell_where_the_code_starts() {
+        let options = BundleOptions {
+            bundle_type: BundleType::Classic,
+            banner: Some("/*! MIT License */".into()),
+            use_strict: false,
+            ..BundleOptions::default()
+        };
+        let wrapper = Wrapper::new(&options, false).unwrap();
+        let code = wrapper.wrap("console.log(1);");
+        let (line, col) = wrapper.body_start();
+        let start = code.lines().nth(line as usize).unwrap();
+        assert!(start[col as usize..].starts_with("console.log(1);"));
+        assert!(col > 0);
+    }
+
+    #[test]
+    fn wrap_code_should_use_custom_template() {
+        let options = BundleOptions {
+            template: Some("host.register((exports) => {\n{{ body }}\n});".into()),
+            ..BundleOptions::default()
+        };
+        let code = wrap_code("console.log(1);", &options, false).unwrap();
+        assert_eq!(code, "host.register((exports) => {\nconsole.log(1);\n});");
+
+        let options = BundleOptions {
+            template: Some("host.register()".into()),
+            ..BundleOptions::default()
+        };
+        assert!(wrap_code("console.log(1);", &options, false).is_err());
+    }
+
+    #[test]
+    fn wrap_code_should_define_bundle_url_for_workers() {
+        let options = BundleOptions {
+            bundle_type: BundleType::Module,
+            ..BundleOptions::default()
+        };
+        let code = wrap_code("console.log(1);", &options, true).unwrap();
+        assert!(code.contains(MODULE_BUNDLE_URL));
+
+        let options = BundleOptions {
+            bundle_type: BundleType::Classic,
+            ..BundleOptions::default()
+        };
+        let code = wrap_code("console.log(1);", &options, true).unwrap();
+        assert!(code.contains(SCRIPT_BUNDLE_URL));
+        let code = wrap_code("console.log(1);", &options, false).unwrap();
+        assert!(!code.contains("__deno_bundle_url"));
+
+        for (bundle_type, bundle_url) in [
+            (BundleType::Module, MODULE_BUNDLE_URL),
+            (BundleType::Classic, SCRIPT_BUNDLE_URL),
+        ] {
+            let options = BundleOptions {
+                bundle_type,
+                template: Some("{{ body }}".into()),
+                ..BundleOptions::default()
+            };
+            let code = wrap_code("console.log(1);", &options, true).unwrap();
+            assert_eq!(code, format!("{}\nconsole.log(1);", bundle_url));
+        }
+    }
+
+    #[test]
+    fn hashed_file_name_should_work() {
+        let output = BundleOutput {
+            code: "console.log(1);".into(),
+            maybe_map: None,
+            maybe_metafile: None,
+            dropped_exports: Default::default(),
+            maybe_hash: Some(content_hash("console.log(1);")),
+            workers: Default::default(),
+            chunks: Default::default(),
+        };
+        let hash = &output.maybe_hash.as_ref().unwrap()[..8];
+        assert_eq!(
+            output.hashed_file_name("app.js"),
+            format!("app.{}.js", hash)
+        );
+        assert_eq!(output.hashed_file_name("app"), format!("app.{}", hash));
+        assert_eq!(
+            output.hashed_file_name("dist.v1/app"),
+            format!("dist.v1/app.{}", hash)
+        );
+    }
+}
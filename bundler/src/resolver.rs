@@ -14,10 +14,10 @@ impl swc::bundler::Resolve for BundleResolver<'_> {
         let referrer = if let swc::common::FileName::Url(referrer) = referrer {
             referrer
         } else {
-            unreachable!(
+            return Err(anyhow!(
                 "An unexpected referrer was passed when bundling: {:?}",
                 referrer
-            );
+            ));
         };
         if let Some(specifier) = self.0.resolve_dependency(specifier, referrer, false) {
             Ok(deno_ast::swc::common::FileName::Url(specifier.clone()))
@@ -0,0 +1,180 @@
+use deno_core::{
+    error::AnyError,
+    futures::{future, FutureExt},
+    ModuleSpecifier,
+};
+use deno_graph::source::{LoadFuture, Loader};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// A token to cancel a running [crate::bundle_async]. Clones share the same
+/// state, so the token could be cancelled from any thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    parent: Option<Box<CancellationToken>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token which is cancelled with this one, but cancelling it leaves this
+    /// one untouched.
+    pub fn child_token(&self) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self.parent.as_ref().map_or(false, |p| p.is_cancelled())
+    }
+}
+
+/// Cancel the token when dropped, unless disarmed, e.g. when the future of
+/// [crate::bundle_async] is dropped before the bundling finishes.
+#[cfg(feature = "async")]
+pub(crate) struct CancelOnDrop(Option<CancellationToken>);
+
+#[cfg(feature = "async")]
+impl CancelOnDrop {
+    pub fn new(token: CancellationToken) -> Self {
+        Self(Some(token))
+    }
+
+    pub fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+/// The error returned when the bundling is cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BundleCancelled;
+
+impl fmt::Display for BundleCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bundling was cancelled.")
+    }
+}
+
+impl std::error::Error for BundleCancelled {}
+
+/// The progress of a running bundle. The events are reported for the entry and
+/// then for each worker chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleProgress {
+    /// the module graph of the root is loaded
+    GraphBuilt {
+        root: ModuleSpecifier,
+        modules: usize,
+    },
+    /// `done` of the `total` modules are transpiled
+    Transpiled { done: usize, total: usize },
+    /// the bundled code is being minified, only if `minify` is set
+    Minifying,
+    /// the bundled code is being emitted
+    Emitting,
+}
+
+/// Cancellation and progress reporting shared by the phases of a bundle.
+#[derive(Clone, Default)]
+pub(crate) struct Progress {
+    token: CancellationToken,
+    maybe_callback: Option<Arc<dyn Fn(BundleProgress) + Send + Sync>>,
+}
+
+impl Progress {
+    pub fn new(
+        token: CancellationToken,
+        callback: impl Fn(BundleProgress) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            token,
+            maybe_callback: Some(Arc::new(callback)),
+        }
+    }
+
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    pub fn report(&self, event: BundleProgress) {
+        if let Some(callback) = &self.maybe_callback {
+            callback(event);
+        }
+    }
+
+    /// Return [BundleCancelled] if the token is cancelled.
+    pub fn check(&self) -> Result<(), AnyError> {
+        if self.token.is_cancelled() {
+            return Err(BundleCancelled.into());
+        }
+        Ok(())
+    }
+}
+
+/// A graph loader which fails all the loads once the token is cancelled, so
+/// that building the graph stops early.
+pub(crate) struct CancellableLoader<'a> {
+    pub inner: &'a mut dyn Loader,
+    pub token: CancellationToken,
+}
+
+impl Loader for CancellableLoader<'_> {
+    fn load(&mut self, specifier: &ModuleSpecifier, is_dynamic: bool) -> LoadFuture {
+        if self.token.is_cancelled() {
+            return future::ready(Err(BundleCancelled.into())).boxed_local();
+        }
+        self.inner.load(specifier, is_dynamic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_should_check_the_token() {
+        let token = CancellationToken::new();
+        let progress = Progress::new(token.clone(), |_| {});
+        assert!(progress.check().is_ok());
+        token.cancel();
+        let err = progress.check().unwrap_err();
+        assert!(err.downcast_ref::<BundleCancelled>().is_some());
+    }
+
+    #[test]
+    fn child_token_should_not_cancel_its_parent() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled());
+
+        let child = parent.child_token();
+        parent.cancel();
+        assert!(child.is_cancelled());
+    }
+}
@@ -0,0 +1,632 @@
+use deno_ast::swc::{
+    ast::{
+        Callee, Class, Decl, DefaultDecl, ExportSpecifier, Expr, Function, Ident, ImportDecl,
+        ImportSpecifier, Lit, Module, ModuleDecl, ModuleExportName, ModuleItem, ObjectPatProp, Pat,
+        Stmt,
+    },
+    common::{BytePos, Span, Spanned},
+    visit::{Visit, VisitWith},
+};
+use deno_core::{
+    serde_json::{self, Value},
+    ModuleSpecifier,
+};
+use deno_graph::{
+    source::{LoadResponse, Loader},
+    ModuleGraph,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Whether the top level code of a module has side effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SideEffects {
+    /// The module is free of side effects, so it could be dropped if nothing
+    /// imported from it is used.
+    Free,
+    /// The module must be kept even if nothing imported from it is used.
+    Keep,
+}
+
+/// Annotate the modules matching `pattern` with their side effects. The
+/// pattern is either a full specifier or a glob (e.g.
+/// `https://esm.sh/lodash-es@4.17.21/**`). When several rules match a module,
+/// the last one wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SideEffectsRule {
+    pub pattern: String,
+    pub side_effects: SideEffects,
+}
+
+impl SideEffectsRule {
+    pub fn new(pattern: impl Into<String>, side_effects: SideEffects) -> Self {
+        Self {
+            pattern: pattern.into(),
+            side_effects,
+        }
+    }
+
+    /// Convert the `sideEffects` field of a `package.json` into rules for the
+    /// package located at `package_url`:
+    ///
+    /// - `"sideEffects": false` marks every module of the package free of side
+    ///   effects.
+    /// - `"sideEffects": ["*.css", "./src/polyfill.js"]` marks every module free
+    ///   of side effects except the ones matched by the globs.
+    pub fn from_package_json(package_url: &ModuleSpecifier, package_json: &Value) -> Vec<Self> {
+        let base = package_url.as_str().trim_end_matches('/');
+        let all = format!("{}/**", base);
+        match package_json.get("sideEffects") {
+            Some(Value::Bool(false)) => vec![Self::new(all, SideEffects::Free)],
+            Some(Value::Array(globs)) => {
+                let mut rules = vec![Self::new(all, SideEffects::Free)];
+                rules.extend(globs.iter().filter_map(|g| g.as_str()).map(|g| {
+                    // same as webpack, a glob without slash matches files in any directory
+                    let pattern = match g.strip_prefix("./") {
+                        Some(g) => format!("{}/{}", base, g),
+                        None if !g.contains('/') => format!("{}/**/{}", base, g),
+                        None => format!("{}/{}", base, g.trim_start_matches('/')),
+                    };
+                    Self::new(pattern, SideEffects::Keep)
+                }));
+                rules
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Load the closest `package.json` of each module in the graph and turn
+    /// its `sideEffects` field into rules. The modules of a package without
+    /// the field are kept, even if an enclosing package marks them as free of
+    /// side effects. The rules of the inner packages come later, so they win.
+    /// Every directory is looked up only once, and a `package.json` which
+    /// can't be loaded or parsed is skipped.
+    pub async fn from_graph(graph: &ModuleGraph, loader: &mut dyn Loader) -> Vec<Self> {
+        let mut visited = HashSet::new();
+        let mut packages = Vec::new();
+        for m in graph.modules() {
+            if !matches!(m.specifier.scheme(), "file" | "http" | "https") {
+                continue;
+            }
+            let mut dir = match m.specifier.join("./") {
+                Ok(dir) => dir,
+                Err(_) => continue,
+            };
+            while visited.insert(dir.clone()) {
+                if let Some(package_json) = load_package_json(loader, &dir).await {
+                    let mut rules = Self::from_package_json(&dir, &package_json);
+                    if rules.is_empty() {
+                        let all = format!("{}**", dir);
+                        rules.push(Self::new(all, SideEffects::Keep));
+                    }
+                    packages.push((dir, rules));
+                    break;
+                }
+                match dir.join("../") {
+                    Ok(parent) if parent != dir => dir = parent,
+                    _ => break,
+                }
+            }
+        }
+        packages.sort_by(|(a, _), (b, _)| (a.as_str().len(), a).cmp(&(b.as_str().len(), b)));
+        packages.into_iter().flat_map(|(_, rules)| rules).collect()
+    }
+
+    fn matches(&self, specifier: &ModuleSpecifier) -> bool {
+        self.pattern == specifier.as_str()
+            || glob::Pattern::new(&self.pattern)
+                .map(|p| p.matches(specifier.as_str()))
+                .unwrap_or(false)
+    }
+}
+
+async fn load_package_json(loader: &mut dyn Loader, dir: &ModuleSpecifier) -> Option<Value> {
+    let specifier = dir.join("package.json").ok()?;
+    match loader.load(&specifier, false).await {
+        Ok(Some(LoadResponse::Module { content, .. })) => serde_json::from_str(&content).ok(),
+        _ => None,
+    }
+}
+
+/// Find out the side effects of a module by the given rules. `None` means the
+/// module has no annotation and will be treated as having side effects.
+pub fn get_side_effects(
+    rules: &[SideEffectsRule],
+    specifier: &ModuleSpecifier,
+) -> Option<SideEffects> {
+    rules
+        .iter()
+        .rev()
+        .find(|r| r.matches(specifier))
+        .map(|r| r.side_effects)
+}
+
+/// Remove the imports of side effect free modules whose bindings are never
+/// used by the importing module. Bare imports like `import "./mod.ts"` are
+/// removed as well.
+pub fn remove_unused_imports(
+    module: &mut Module,
+    rules: &[SideEffectsRule],
+    resolve: impl Fn(&str) -> Option<ModuleSpecifier>,
+) {
+    if rules.is_empty() {
+        return;
+    }
+    let mut used = UsedIdents::default();
+    for item in &module.body {
+        if !matches!(item, ModuleItem::ModuleDecl(ModuleDecl::Import(_))) {
+            item.visit_with(&mut used);
+        }
+    }
+    module.body.retain(|item| match item {
+        ModuleItem::ModuleDecl(ModuleDecl::Import(import)) if !import.type_only => {
+            let free = resolve(&import.src.value)
+                .and_then(|s| get_side_effects(rules, &s))
+                .map(|s| s == SideEffects::Free)
+                .unwrap_or(false);
+            !free || is_import_used(import, &used.0)
+        }
+        _ => true,
+    });
+}
+
+fn is_import_used(import: &ImportDecl, used: &HashSet<String>) -> bool {
+    import.specifiers.iter().any(|s| {
+        let local = match s {
+            ImportSpecifier::Named(s) => &s.local,
+            ImportSpecifier::Default(s) => &s.local,
+            ImportSpecifier::Namespace(s) => &s.local,
+        };
+        used.contains(&*local.sym)
+    })
+}
+
+/// Collect the names of all identifiers referenced in a module. Shadowed names
+/// are counted as well, which keeps the removal of imports conservative.
+#[derive(Default)]
+struct UsedIdents(HashSet<String>);
+
+impl Visit for UsedIdents {
+    fn visit_ident(&mut self, ident: &deno_ast::swc::ast::Ident) {
+        self.0.insert(ident.sym.to_string());
+    }
+}
+
+/// An export of a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    /// position of the exported binding, `None` if it isn't declared by the
+    /// module, e.g. a re-export
+    pub maybe_pos: Option<BytePos>,
+    /// whether the module itself references the binding
+    pub used_locally: bool,
+}
+
+/// The exports of a module and what it imports from other modules.
+#[derive(Debug, Default, Clone)]
+pub struct ModuleInfo {
+    pub exports: Vec<Export>,
+    /// imported names keyed by the raw import specifier, `None` means all the
+    /// exports of the imported module are used (e.g. a namespace import).
+    pub imports: HashMap<String, Option<HashSet<String>>>,
+}
+
+impl ModuleInfo {
+    pub fn new(module: &Module) -> Self {
+        let mut bindings = Bindings::default();
+        for item in &module.body {
+            match item {
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => {
+                    bindings.add_decl(&export.decl)
+                }
+                ModuleItem::Stmt(Stmt::Decl(decl)) => bindings.add_decl(decl),
+                _ => {}
+            }
+        }
+        let mut refs = LocalRefs {
+            decls: bindings.0.values().collect(),
+            names: HashSet::new(),
+        };
+        for item in &module.body {
+            // the specifiers of the imports and exports are not references
+            if !matches!(
+                item,
+                ModuleItem::ModuleDecl(ModuleDecl::Import(_) | ModuleDecl::ExportNamed(_))
+            ) {
+                item.visit_with(&mut refs);
+            }
+        }
+        let names = refs.names;
+
+        let mut info = ModuleInfo::default();
+        for item in &module.body {
+            match item {
+                ModuleItem::ModuleDecl(decl) => info.add_module_decl(decl, &bindings, &names),
+                ModuleItem::Stmt(_) => {}
+            }
+        }
+        let mut dynamic = DynamicImports::default();
+        module.visit_with(&mut dynamic);
+        for specifier in dynamic.0 {
+            info.import_all(specifier);
+        }
+        info
+    }
+
+    fn export(&mut self, name: String, maybe_pos: Option<BytePos>, used_locally: bool) {
+        self.exports.push(Export {
+            name,
+            maybe_pos,
+            used_locally,
+        });
+    }
+
+    fn add_module_decl(&mut self, decl: &ModuleDecl, bindings: &Bindings, refs: &HashSet<String>) {
+        match decl {
+            ModuleDecl::Import(import) => {
+                let src = import.src.value.to_string();
+                for s in &import.specifiers {
+                    match s {
+                        ImportSpecifier::Named(s) => {
+                            let name = match &s.imported {
+                                Some(name) => export_name(name),
+                                None => s.local.sym.to_string(),
+                            };
+                            self.import(src.clone(), name);
+                        }
+                        ImportSpecifier::Default(_) => self.import(src.clone(), "default".into()),
+                        ImportSpecifier::Namespace(_) => self.import_all(src.clone()),
+                    }
+                }
+            }
+            ModuleDecl::ExportDecl(export) => {
+                let mut declared = Bindings::default();
+                declared.add_decl(&export.decl);
+                for (name, pos) in declared.0 {
+                    let used_locally = refs.contains(&name);
+                    self.export(name, Some(pos), used_locally);
+                }
+            }
+            ModuleDecl::ExportNamed(export) => {
+                for s in &export.specifiers {
+                    match s {
+                        ExportSpecifier::Named(s) => {
+                            let orig = export_name(&s.orig);
+                            let exported = s.exported.as_ref().map(export_name);
+                            let exported = exported.unwrap_or_else(|| orig.clone());
+                            match export.src.as_ref() {
+                                Some(src) => {
+                                    self.export(exported, None, false);
+                                    self.import(src.value.to_string(), orig);
+                                }
+                                None => {
+                                    let maybe_pos = bindings.0.get(&orig).copied();
+                                    self.export(exported, maybe_pos, refs.contains(&orig));
+                                }
+                            }
+                        }
+                        ExportSpecifier::Namespace(s) => {
+                            self.export(export_name(&s.name), None, false);
+                            if let Some(src) = export.src.as_ref() {
+                                self.import_all(src.value.to_string());
+                            }
+                        }
+                        ExportSpecifier::Default(s) => {
+                            self.export(s.exported.sym.to_string(), None, false);
+                            if let Some(src) = export.src.as_ref() {
+                                self.import(src.value.to_string(), "default".into());
+                            }
+                        }
+                    }
+                }
+            }
+            ModuleDecl::ExportDefaultDecl(export) => {
+                let (maybe_ident, span) = match &export.decl {
+                    DefaultDecl::Class(c) => (c.ident.as_ref(), c.class.span),
+                    DefaultDecl::Fn(f) => (f.ident.as_ref(), f.function.span),
+                    DefaultDecl::TsInterfaceDecl(_) => return,
+                };
+                let used_locally = maybe_ident
+                    .map(|ident| refs.contains(&*ident.sym))
+                    .unwrap_or(false);
+                self.export("default".into(), pos(span), used_locally);
+            }
+            ModuleDecl::ExportDefaultExpr(export) => {
+                self.export("default".into(), pos(export.expr.span()), false)
+            }
+            // we don't know the names re-exported, so treat every export of the
+            // source module as used
+            ModuleDecl::ExportAll(export) => self.import_all(export.src.value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn import(&mut self, src: String, name: String) {
+        if let Some(names) = self
+            .imports
+            .entry(src)
+            .or_insert_with(|| Some(HashSet::new()))
+        {
+            names.insert(name);
+        }
+    }
+
+    fn import_all(&mut self, src: String) {
+        self.imports.insert(src, None);
+    }
+}
+
+fn export_name(name: &ModuleExportName) -> String {
+    match name {
+        ModuleExportName::Ident(ident) => ident.sym.to_string(),
+        ModuleExportName::Str(s) => s.value.to_string(),
+    }
+}
+
+fn pos(span: Span) -> Option<BytePos> {
+    (!span.is_dummy()).then(|| span.lo)
+}
+
+/// The top level bindings of a module and the positions of their declarations.
+#[derive(Default)]
+struct Bindings(BTreeMap<String, BytePos>);
+
+impl Bindings {
+    fn add_decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Class(c) => self.add(&c.ident),
+            Decl::Fn(f) => self.add(&f.ident),
+            Decl::Var(v) => {
+                for d in &v.decls {
+                    self.add_pat(&d.name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn add(&mut self, ident: &Ident) {
+        if let Some(pos) = pos(ident.span) {
+            self.0.insert(ident.sym.to_string(), pos);
+        }
+    }
+
+    fn add_pat(&mut self, pat: &Pat) {
+        match pat {
+            Pat::Ident(ident) => self.add(&ident.id),
+            Pat::Array(arr) => arr.elems.iter().flatten().for_each(|p| self.add_pat(p)),
+            Pat::Object(obj) => {
+                for prop in &obj.props {
+                    match prop {
+                        ObjectPatProp::KeyValue(kv) => self.add_pat(&kv.value),
+                        ObjectPatProp::Assign(a) => self.add(&a.key),
+                        ObjectPatProp::Rest(r) => self.add_pat(&r.arg),
+                    }
+                }
+            }
+            Pat::Rest(r) => self.add_pat(&r.arg),
+            Pat::Assign(a) => self.add_pat(&a.left),
+            _ => {}
+        }
+    }
+}
+
+/// Collect the names referenced by a module, except the declarations of the
+/// top level bindings. Shadowed names are counted as well, which keeps the
+/// report of the dropped exports conservative.
+struct LocalRefs<'a> {
+    decls: HashSet<&'a BytePos>,
+    names: HashSet<String>,
+}
+
+impl Visit for LocalRefs<'_> {
+    fn visit_ident(&mut self, ident: &Ident) {
+        if !self.decls.contains(&ident.span.lo) {
+            self.names.insert(ident.sym.to_string());
+        }
+    }
+}
+
+/// Collect the positions of the nodes which may hold the declaration of an
+/// export, so that the exports still in the output could be found.
+#[derive(Default)]
+struct Positions(HashSet<BytePos>);
+
+impl Visit for Positions {
+    fn visit_ident(&mut self, ident: &Ident) {
+        self.0.insert(ident.span.lo);
+    }
+
+    fn visit_function(&mut self, f: &Function) {
+        self.0.insert(f.span.lo);
+        f.visit_children_with(self);
+    }
+
+    fn visit_class(&mut self, c: &Class) {
+        self.0.insert(c.span.lo);
+        c.visit_children_with(self);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.0.insert(expr.span().lo);
+        expr.visit_children_with(self);
+    }
+}
+
+/// Collect `import("./mod.ts")` calls with a static specifier.
+#[derive(Default)]
+struct DynamicImports(Vec<String>);
+
+impl Visit for DynamicImports {
+    fn visit_call_expr(&mut self, call: &deno_ast::swc::ast::CallExpr) {
+        if let Callee::Import(_) = call.callee {
+            if let Some(Expr::Lit(Lit::Str(s))) = call.args.first().map(|a| &*a.expr) {
+                self.0.push(s.value.to_string());
+            }
+        }
+        call.visit_children_with(self);
+    }
+}
+
+/// Report the exports of each module which are never imported by other modules
+/// in the graph and have been removed from the minified `output`. Exports used
+/// by their own module, or whose declaration can't be found, are not reported.
+/// The exports of the entry module are always kept.
+pub fn get_dropped_exports(
+    graph: &ModuleGraph,
+    infos: &HashMap<ModuleSpecifier, ModuleInfo>,
+    output: &Module,
+) -> BTreeMap<String, Vec<String>> {
+    let mut kept = Positions::default();
+    output.visit_with(&mut kept);
+
+    let mut used: HashMap<&ModuleSpecifier, Option<HashSet<&str>>> = HashMap::new();
+    for (referrer, info) in infos {
+        for (src, names) in &info.imports {
+            let specifier = match graph.resolve_dependency(src, referrer, false) {
+                Some(s) => s,
+                None => continue,
+            };
+            match names {
+                Some(names) => {
+                    if let Some(set) = used
+                        .entry(specifier)
+                        .or_insert_with(|| Some(HashSet::new()))
+                    {
+                        set.extend(names.iter().map(|s| s.as_str()));
+                    }
+                }
+                None => {
+                    used.insert(specifier, None);
+                }
+            }
+        }
+    }
+
+    let roots: Vec<_> = graph.roots.iter().map(|(s, _)| s).collect();
+    infos
+        .iter()
+        .filter(|(specifier, _)| !roots.contains(specifier))
+        .filter_map(|(specifier, info)| {
+            let used = match used.get(specifier) {
+                Some(None) => return None,
+                Some(Some(used)) => Some(used),
+                None => None,
+            };
+            let dropped: Vec<_> = info
+                .exports
+                .iter()
+                .filter(|e| !used.map(|u| u.contains(e.name.as_str())).unwrap_or(false))
+                .filter(|e| !e.used_locally)
+                .filter(|e| matches!(e.maybe_pos, Some(pos) if !kept.0.contains(&pos)))
+                .map(|e| e.name.clone())
+                .collect();
+            (!dropped.is_empty()).then(|| (specifier.to_string(), dropped))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VirtualFs;
+    use deno_ast::swc::{
+        common::{sync::Lrc, FileName, SourceMap},
+        parser::{lexer::Lexer, Parser, StringInput, Syntax},
+    };
+    use deno_core::{resolve_url, serde_json::json};
+
+    #[test]
+    fn module_info_should_find_exports_used_locally() {
+        let code = r#"
+export function add(a, b) { return round(a + b); }
+export function round(n) { return Math.round(n); }
+const PI = 3.14;
+export { PI as pi };
+export { sub } from "./sub.js";
+"#;
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Anon, code.to_string());
+        let lexer = Lexer::new(
+            Syntax::Es(Default::default()),
+            deno_ast::ES_VERSION,
+            StringInput::from(&*fm),
+            None,
+        );
+        let module = Parser::new_from(lexer).parse_module().unwrap();
+        let info = ModuleInfo::new(&module);
+        let export = |name: &str| info.exports.iter().find(|e| e.name == name).unwrap();
+        assert!(!export("add").used_locally);
+        assert!(export("round").used_locally);
+        assert!(!export("pi").used_locally);
+        assert!(export("pi").maybe_pos.is_some());
+        assert_eq!(export("sub").maybe_pos, None);
+    }
+
+    #[tokio::test]
+    async fn side_effects_rules_from_graph_should_read_package_json() {
+        let mut fs = VirtualFs::new();
+        let m = fs
+            .insert(
+                "file:///app/main.js",
+                "import './node_modules/a/index.js';\nimport './node_modules/a/node_modules/b/index.js';",
+            )
+            .unwrap();
+        fs.insert("file:///app/node_modules/a/index.js", "")
+            .unwrap();
+        fs.insert(
+            "file:///app/node_modules/a/package.json",
+            r#"{ "sideEffects": false }"#,
+        )
+        .unwrap();
+        fs.insert("file:///app/node_modules/a/node_modules/b/index.js", "")
+            .unwrap();
+        fs.insert(
+            "file:///app/node_modules/a/node_modules/b/package.json",
+            "{}",
+        )
+        .unwrap();
+        let graph = deno_graph::create_graph(
+            vec![(m, deno_graph::ModuleKind::Esm)],
+            false,
+            None,
+            &mut fs,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+        let rules = SideEffectsRule::from_graph(&graph, &mut fs).await;
+        let check = |s: &str| get_side_effects(&rules, &resolve_url(s).unwrap());
+        assert_eq!(check("file:///app/main.js"), None);
+        assert_eq!(
+            check("file:///app/node_modules/a/index.js"),
+            Some(SideEffects::Free)
+        );
+        // the closest package.json wins
+        assert_eq!(
+            check("file:///app/node_modules/a/node_modules/b/index.js"),
+            Some(SideEffects::Keep)
+        );
+    }
+
+    #[test]
+    fn side_effects_rules_from_package_json_should_work() {
+        let base = resolve_url("https://esm.sh/foo@1.0.0/").unwrap();
+        let rules = SideEffectsRule::from_package_json(
+            &base,
+            &json!({ "sideEffects": ["*.css", "./src/polyfill.js"] }),
+        );
+        let check = |path: &str| get_side_effects(&rules, &base.join(path).unwrap());
+        assert_eq!(check("index.js"), Some(SideEffects::Free));
+        assert_eq!(check("lib/styles/main.css"), Some(SideEffects::Keep));
+        assert_eq!(check("src/polyfill.js"), Some(SideEffects::Keep));
+
+        let other = resolve_url("https://esm.sh/bar@1.0.0/index.js").unwrap();
+        assert_eq!(get_side_effects(&rules, &other), None);
+
+        let rules = SideEffectsRule::from_package_json(&base, &json!({ "sideEffects": true }));
+        assert!(rules.is_empty());
+    }
+}
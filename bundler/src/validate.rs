@@ -0,0 +1,102 @@
+use deno_core::ModuleSpecifier;
+use deno_graph::{ModuleGraph, Range, Resolved};
+use std::fmt;
+
+use crate::check::Position;
+
+/// An import which could not be resolved or loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnresolvedImport {
+    /// the specifier as written in the import, or the root module
+    pub specifier: String,
+    /// the module which has the import, `None` for the root module
+    pub maybe_referrer: Option<ModuleSpecifier>,
+    /// where the specifier is in the referrer
+    pub maybe_position: Option<Position>,
+    pub message: String,
+}
+
+/// The error returned by [crate::bundle] when the module graph is invalid.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnresolvedImports(pub Vec<UnresolvedImport>);
+
+impl fmt::Display for UnresolvedImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unable to load \"{}\": {}", self.specifier, self.message)?;
+        if let Some(referrer) = &self.maybe_referrer {
+            write!(f, "\n    at {}", referrer)?;
+            if let Some(pos) = &self.maybe_position {
+                write!(f, ":{}:{}", pos.line + 1, pos.character + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UnresolvedImports {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, item) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnresolvedImports {}
+
+/// Make sure that the roots and all the code imports in the graph are
+/// resolved and loaded.
+pub fn validate_graph(graph: &ModuleGraph) -> Result<(), UnresolvedImports> {
+    let mut items = Vec::new();
+    for (root, _) in &graph.roots {
+        let message = match graph.try_get(root) {
+            Ok(Some(_)) => continue,
+            Ok(None) => "Module not found.".to_string(),
+            Err(e) => e.to_string(),
+        };
+        items.push(UnresolvedImport {
+            specifier: root.to_string(),
+            maybe_referrer: None,
+            maybe_position: None,
+            message,
+        });
+    }
+
+    let mut modules = graph.modules();
+    modules.sort_by(|a, b| a.specifier.cmp(&b.specifier));
+    for m in modules {
+        for (raw, dep) in &m.dependencies {
+            let (position, message) = match &dep.maybe_code {
+                Resolved::None => continue,
+                Resolved::Err(e) => (to_position(e.range()), e.to_string()),
+                Resolved::Ok { specifier, range } => match graph.try_get(specifier) {
+                    Ok(Some(_)) => continue,
+                    Ok(None) => (to_position(range), "Module not found.".to_string()),
+                    Err(e) => (to_position(range), e.to_string()),
+                },
+            };
+            items.push(UnresolvedImport {
+                specifier: raw.clone(),
+                maybe_referrer: Some(m.specifier.clone()),
+                maybe_position: Some(position),
+                message,
+            });
+        }
+    }
+
+    if items.is_empty() {
+        Ok(())
+    } else {
+        Err(UnresolvedImports(items))
+    }
+}
+
+fn to_position(range: &Range) -> Position {
+    Position {
+        line: range.start.line as u32,
+        character: range.start.character as u32,
+    }
+}
@@ -0,0 +1,73 @@
+use crate::assets::{AssetSource, LoadBytesFuture};
+use deno_core::{error::AnyError, futures::FutureExt, resolve_url, ModuleSpecifier};
+use deno_graph::source::{LoadFuture, LoadResponse, Loader};
+use std::{collections::HashMap, sync::Arc};
+
+/// An in-memory file system for bundling. Modules are only loaded from the
+/// sources added to it, so bundling never touches the disk or the network.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualFs {
+    files: HashMap<ModuleSpecifier, Arc<str>>,
+    /// the binary files, which are only loaded as assets
+    binaries: HashMap<ModuleSpecifier, Arc<[u8]>>,
+}
+
+impl VirtualFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the source of a module, the specifier must be a valid url (e.g.
+    /// `file:///main.ts`), relative imports are resolved against it.
+    pub fn insert(
+        &mut self,
+        specifier: impl AsRef<str>,
+        source: impl Into<Arc<str>>,
+    ) -> Result<ModuleSpecifier, AnyError> {
+        let specifier = resolve_url(specifier.as_ref())?;
+        self.files.insert(specifier.clone(), source.into());
+        Ok(specifier)
+    }
+
+    /// Add the raw content of a file, e.g. a wasm asset, it can only be
+    /// imported through an asset rule.
+    pub fn insert_bytes(
+        &mut self,
+        specifier: impl AsRef<str>,
+        bytes: impl Into<Arc<[u8]>>,
+    ) -> Result<ModuleSpecifier, AnyError> {
+        let specifier = resolve_url(specifier.as_ref())?;
+        self.binaries.insert(specifier.clone(), bytes.into());
+        Ok(specifier)
+    }
+
+    pub fn get(&self, specifier: &ModuleSpecifier) -> Option<&Arc<str>> {
+        self.files.get(specifier)
+    }
+}
+
+impl Loader for VirtualFs {
+    fn load(&mut self, specifier: &ModuleSpecifier, _is_dynamic: bool) -> LoadFuture {
+        // a module which is not in the file system ends up as a missing module
+        // in the graph
+        let response = self
+            .files
+            .get(specifier)
+            .map(|content| LoadResponse::Module {
+                content: content.clone(),
+                specifier: specifier.clone(),
+                maybe_headers: None,
+            });
+        async move { Ok(response) }.boxed_local()
+    }
+}
+
+impl AssetSource for VirtualFs {
+    fn load_bytes(&mut self, specifier: &ModuleSpecifier) -> LoadBytesFuture {
+        let bytes = match self.binaries.get(specifier) {
+            Some(bytes) => Some(bytes.to_vec()),
+            None => self.files.get(specifier).map(|s| s.as_bytes().to_vec()),
+        };
+        async move { Ok(bytes) }.boxed_local()
+    }
+}
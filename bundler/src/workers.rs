@@ -0,0 +1,348 @@
+use deno_ast::swc::{
+    ast::{
+        Expr, ExprOrSpread, Ident, Lit, MemberExpr, MemberProp, MetaPropKind, Module, NewExpr,
+        ObjectLit, Prop, PropName, PropOrSpread, Str,
+    },
+    common::DUMMY_SP,
+    visit::{VisitMut, VisitMutWith},
+};
+use deno_core::ModuleSpecifier;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{output::content_hash, BundleOutput};
+
+/// number of hex characters of the hash in the chunk names
+const CHUNK_NAME_HASH_LEN: usize = 8;
+
+/// The global which holds the url of the bundle, the urls of the worker chunks
+/// are resolved against it.
+pub const BUNDLE_URL: &str = "__deno_bundle_url";
+
+/// A worker created by the bundled code, which is bundled as a separate chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerEntry {
+    /// specifier of the worker module
+    pub specifier: String,
+    /// whether it's created with `{ type: "module" }`
+    pub module: bool,
+}
+
+/// Find the workers created with `new Worker(new URL("./worker.ts",
+/// import.meta.url))` in the module, and rewrite the url to the chunk of the
+/// worker, which is put next to the bundle. The workers are keyed by the
+/// provisional file names of their chunks, see [name_chunks].
+pub fn collect_workers(
+    module: &mut Module,
+    specifier: &ModuleSpecifier,
+    workers: &mut BTreeMap<String, WorkerEntry>,
+) {
+    module.visit_mut_with(&mut WorkerCollector { specifier, workers });
+}
+
+/// Provisional file name of the chunk of a worker, e.g. `worker.3f9a1c2b.js`.
+/// The hash of the specifier tells workers with the same name apart until the
+/// chunk is bundled and [name_chunks] names it by its content.
+pub fn get_chunk_name(specifier: &ModuleSpecifier) -> String {
+    let name = specifier
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .unwrap_or_default();
+    let stem = match name.split_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => "worker",
+    };
+    let hash = format!("{:x}", Sha256::digest(specifier.as_str().as_bytes()));
+    format!("{}.{}.js", stem, &hash[..CHUNK_NAME_HASH_LEN])
+}
+
+/// Name the bundled chunks by the hash of their code, so that a chunk gets a
+/// new name whenever its code changes, and point the worker urls of the
+/// bundle and the chunks to the new names. The nested workers are named
+/// first, their names are part of the code of their parents. The chunks of
+/// workers which create each other keep their provisional names.
+///
+/// The new names have the length of the provisional ones, so the source maps
+/// are still valid.
+pub fn name_chunks(output: &mut BundleOutput) {
+    let mut chunks = std::mem::take(&mut output.chunks);
+    let mut names = HashMap::new();
+    let mut visiting = HashSet::new();
+    let ids: Vec<_> = chunks.keys().cloned().collect();
+    for id in &ids {
+        name_chunk(id, &mut chunks, &mut names, &mut visiting);
+    }
+    rename_workers(output, &names);
+    output.chunks = chunks
+        .into_iter()
+        .map(|(id, chunk)| (names[&id].clone(), chunk))
+        .collect();
+}
+
+fn name_chunk(
+    id: &str,
+    chunks: &mut BTreeMap<String, BundleOutput>,
+    names: &mut HashMap<String, String>,
+    visiting: &mut HashSet<String>,
+) {
+    if names.contains_key(id) || !chunks.contains_key(id) {
+        return;
+    }
+    if !visiting.insert(id.to_string()) {
+        // a cycle, the chunk keeps its provisional name
+        names.insert(id.to_string(), id.to_string());
+        return;
+    }
+    let workers: Vec<_> = chunks[id].workers.keys().cloned().collect();
+    for worker in &workers {
+        name_chunk(worker, chunks, names, visiting);
+    }
+    let chunk = chunks.get_mut(id).unwrap();
+    rename_workers(chunk, names);
+    if !names.contains_key(id) {
+        let stem = id.split('.').next().unwrap_or_default();
+        let hash = content_hash(&chunk.code);
+        names.insert(
+            id.to_string(),
+            format!("{}.{}.js", stem, &hash[..CHUNK_NAME_HASH_LEN]),
+        );
+    }
+    visiting.remove(id);
+}
+
+/// Point the worker urls in the code to the named chunks.
+fn rename_workers(output: &mut BundleOutput, names: &HashMap<String, String>) {
+    let workers = std::mem::take(&mut output.workers);
+    for (id, worker) in workers {
+        let name = names.get(&id).cloned().unwrap_or(id.clone());
+        if name != id {
+            output.code = output
+                .code
+                .replace(&format!("./{}", id), &format!("./{}", name));
+        }
+        output.workers.insert(name, worker);
+    }
+    if output.maybe_hash.is_some() {
+        output.maybe_hash = Some(content_hash(&output.code));
+    }
+}
+
+struct WorkerCollector<'a> {
+    specifier: &'a ModuleSpecifier,
+    workers: &'a mut BTreeMap<String, WorkerEntry>,
+}
+
+impl VisitMut for WorkerCollector<'_> {
+    fn visit_mut_new_expr(&mut self, expr: &mut NewExpr) {
+        expr.visit_mut_children_with(self);
+        if !is_ident(&expr.callee, "Worker") {
+            return;
+        }
+        let args = match expr.args.as_mut() {
+            Some(args) if !args.is_empty() && args[0].spread.is_none() => args,
+            _ => return,
+        };
+        let module = args
+            .get(1)
+            .map(|arg| is_module_type(&arg.expr))
+            .unwrap_or(false);
+
+        // new URL("./worker.ts", import.meta.url)
+        let url_args = match &mut *args[0].expr {
+            Expr::New(NewExpr {
+                callee,
+                args: Some(url_args),
+                ..
+            }) if is_ident(&**callee, "URL") && url_args.len() == 2 => url_args,
+            _ => return,
+        };
+        let path = match &*url_args[0].expr {
+            Expr::Lit(Lit::Str(s)) => s.value.to_string(),
+            _ => return,
+        };
+        if !is_import_meta_url(&url_args[1].expr) {
+            return;
+        }
+        let worker = match self.specifier.join(&path) {
+            Ok(worker) => worker,
+            Err(_) => return,
+        };
+
+        let name = get_chunk_name(&worker);
+        url_args[0] = ExprOrSpread {
+            spread: None,
+            expr: Box::new(Expr::Lit(Lit::Str(Str {
+                span: DUMMY_SP,
+                value: format!("./{}", name).into(),
+                raw: None,
+            }))),
+        };
+        url_args[1] = ExprOrSpread {
+            spread: None,
+            expr: Box::new(Expr::Ident(Ident::new(BUNDLE_URL.into(), DUMMY_SP))),
+        };
+        self.workers.insert(
+            name,
+            WorkerEntry {
+                specifier: worker.to_string(),
+                module,
+            },
+        );
+    }
+}
+
+fn is_ident(expr: &Expr, name: &str) -> bool {
+    matches!(expr, Expr::Ident(ident) if &*ident.sym == name)
+}
+
+fn is_import_meta_url(expr: &Expr) -> bool {
+    match expr {
+        Expr::Member(MemberExpr {
+            obj,
+            prop: MemberProp::Ident(prop),
+            ..
+        }) => {
+            matches!(&**obj, Expr::MetaProp(meta) if meta.kind == MetaPropKind::ImportMeta)
+                && &*prop.sym == "url"
+        }
+        _ => false,
+    }
+}
+
+/// Whether the worker options is `{ type: "module" }`.
+fn is_module_type(expr: &Expr) -> bool {
+    let props = match expr {
+        Expr::Object(ObjectLit { props, .. }) => props,
+        _ => return false,
+    };
+    props.iter().any(|prop| match prop {
+        PropOrSpread::Prop(prop) => match &**prop {
+            Prop::KeyValue(kv) => {
+                let is_type = match &kv.key {
+                    PropName::Ident(ident) => &*ident.sym == "type",
+                    PropName::Str(s) => &*s.value == "type",
+                    _ => false,
+                };
+                is_type && matches!(&*kv.value, Expr::Lit(Lit::Str(s)) if &*s.value == "module")
+            }
+            _ => false,
+        },
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::emit_module;
+    use deno_ast::swc::{
+        common::{sync::Lrc, FileName, SourceMap},
+        parser::{lexer::Lexer, Parser, StringInput, Syntax},
+    };
+    use deno_core::resolve_url;
+
+    #[test]
+    fn collect_workers_should_rewrite_urls() {
+        let code = r#"
+const a = new Worker(new URL("./worker.ts", import.meta.url), { type: "module" });
+const b = new Worker(new URL("./other.js", "https://example.com/"));
+"#;
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Anon, code.to_string());
+        let lexer = Lexer::new(
+            Syntax::Es(Default::default()),
+            deno_ast::ES_VERSION,
+            StringInput::from(&*fm),
+            None,
+        );
+        let mut module = Parser::new_from(lexer).parse_module().unwrap();
+        let specifier = resolve_url("file:///app/main.ts").unwrap();
+        let mut workers = BTreeMap::new();
+        collect_workers(&mut module, &specifier, &mut workers);
+
+        let worker = resolve_url("file:///app/worker.ts").unwrap();
+        let name = get_chunk_name(&worker);
+        assert!(name.starts_with("worker."));
+        assert_eq!(
+            workers[&name],
+            WorkerEntry {
+                specifier: worker.to_string(),
+                module: true
+            }
+        );
+        assert_eq!(workers.len(), 1);
+        let code = emit_module(cm, &module, false).unwrap();
+        assert!(code.contains(&format!("new URL(\"./{}\", {})", name, BUNDLE_URL)));
+        assert!(code.contains("./other.js"));
+    }
+
+    fn chunk(code: &str, workers: &[&str]) -> BundleOutput {
+        BundleOutput {
+            code: code.to_string(),
+            maybe_map: None,
+            maybe_metafile: None,
+            dropped_exports: Default::default(),
+            maybe_hash: Some(content_hash(code)),
+            workers: workers
+                .iter()
+                .map(|w| {
+                    let entry = WorkerEntry {
+                        specifier: format!("file:///app/{}", w),
+                        module: true,
+                    };
+                    (w.to_string(), entry)
+                })
+                .collect(),
+            chunks: Default::default(),
+        }
+    }
+
+    #[test]
+    fn name_chunks_should_use_the_content_hash() {
+        let name_for = |worker_code: &str| {
+            let mut output = chunk(
+                r#"new Worker(new URL("./a.00000000.js", __deno_bundle_url));"#,
+                &["a.00000000.js"],
+            );
+            let a = r#"postMessage(new URL("./b.11111111.js", __deno_bundle_url));"#;
+            output
+                .chunks
+                .insert("a.00000000.js".into(), chunk(a, &["b.11111111.js"]));
+            output
+                .chunks
+                .insert("b.11111111.js".into(), chunk(worker_code, &[]));
+            name_chunks(&mut output);
+            output
+        };
+
+        let output = name_for("postMessage(1);");
+        let b = format!("b.{}.js", &content_hash("postMessage(1);")[..8]);
+        let a = output.workers.keys().next().unwrap().clone();
+        assert!(output.code.contains(&format!("./{}", a)));
+        assert_eq!(output.maybe_hash, Some(content_hash(&output.code)));
+        assert!(output.chunks[&a].code.contains(&format!("./{}", b)));
+        assert!(output.chunks[&a].workers.contains_key(&b));
+        assert_eq!(
+            a,
+            format!("a.{}.js", &content_hash(&output.chunks[&a].code)[..8])
+        );
+
+        // a change in the nested worker renames its parent as well
+        let changed = name_for("postMessage(2);");
+        assert!(!changed.chunks.contains_key(&b));
+        assert!(!changed.chunks.contains_key(&a));
+    }
+
+    #[test]
+    fn name_chunks_should_keep_cycles() {
+        let mut output = chunk(r#"new URL("./a.00000000.js")"#, &["a.00000000.js"]);
+        output.chunks.insert(
+            "a.00000000.js".into(),
+            chunk(r#"new URL("./a.00000000.js")"#, &["a.00000000.js"]),
+        );
+        name_chunks(&mut output);
+        assert!(output.chunks.contains_key("a.00000000.js"));
+        assert!(output.code.contains("./a.00000000.js"));
+    }
+}
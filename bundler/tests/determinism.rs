@@ -0,0 +1,27 @@
+//! The hash maps of each process are seeded differently, so the bundles made by
+//! separate processes of the cli are compared.
+#![cfg(feature = "cli")]
+
+use std::{path::Path, process::Command};
+
+fn bundle_in_process(entry: &Path) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_deno-bundler"))
+        .arg(entry)
+        .arg("--minify")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+#[test]
+fn bundles_of_separate_processes_should_be_identical() {
+    let entry = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/04_tree_shaking.ts");
+    let code = bundle_in_process(&entry);
+    assert!(!code.is_empty());
+    assert_eq!(code, bundle_in_process(&entry));
+}
@@ -0,0 +1,28 @@
+#!/usr/bin/env bash
+# Vendor the typescript compiler and the `.d.ts` files embedded by the `tsc`
+# feature: the standard libraries of typescript, and the deno libraries of the
+# deno release built with the same deno_core and typescript.
+set -euo pipefail
+
+TYPESCRIPT_VERSION=4.7.4
+DENO_VERSION=1.24.3
+DENO=https://raw.githubusercontent.com/denoland/deno/v$DENO_VERSION
+
+cd "$(dirname "$0")"
+rm -rf typescript.js dts
+mkdir -p dts
+
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+curl -sSfL "https://registry.npmjs.org/typescript/-/typescript-$TYPESCRIPT_VERSION.tgz" |
+  tar -xz -C "$tmp"
+cp "$tmp/package/lib/typescript.js" typescript.js
+cp "$tmp"/package/lib/lib.*.d.ts dts/
+
+for name in ns shared_globals unstable window worker; do
+  curl -sSfL "$DENO/cli/dts/lib.deno.$name.d.ts" -o "dts/lib.deno.$name.d.ts"
+done
+# referenced as e.g. `/// <reference lib="deno.console" />`
+for ext in broadcast_channel console crypto fetch net url web webgpu websocket webstorage; do
+  curl -sSfL "$DENO/ext/$ext/lib.deno_$ext.d.ts" -o "dts/lib.deno.$ext.d.ts"
+done
@@ -12,6 +12,7 @@ pub static CLI_SNAPSHOT: Lazy<Box<[u8]>> = Lazy::new(
             include_bytes!(concat!(env!("OUT_DIR"), "/CLI_SNAPSHOT.bin"));
 
         deno_snapshot::decode(COMPRESSED_CLI_SNAPSHOT)
+            .unwrap_or_else(|e| panic!("Invalid runtime snapshot: {}", e))
     },
 );
 
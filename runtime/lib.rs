@@ -23,6 +23,7 @@ pub mod errors;
 pub mod fs_util;
 pub mod inspector_server;
 pub mod js;
+pub mod limits;
 pub mod ops;
 pub mod permissions;
 pub mod tokio_util;
@@ -38,8 +39,15 @@ pub use worker::{MainWorker, WorkerOptions, WorkerOptionsBuilder, WorkerOptionsB
 
 #[cfg(test)]
 pub mod test_util {
-    use crate::{permissions::Permissions, MainWorker, WorkerOptionsBuilder};
-    use std::path::PathBuf;
+    use crate::{
+        permissions::Permissions,
+        web_worker::{WebWorker, WebWorkerOptions, WebWorkerType, WorkerId},
+        BootstrapOptions, MainWorker, WorkerOptionsBuilder,
+    };
+    use deno_broadcast_channel::InMemoryBroadcastChannel;
+    use deno_core::{resolve_url, FsModuleLoader};
+    use deno_web::BlobStore;
+    use std::{path::PathBuf, rc::Rc, sync::Arc};
 
     pub fn testdata_path(name: &str) -> String {
         let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -48,12 +56,52 @@ pub mod test_util {
     }
 
     pub fn create_test_worker(file: impl AsRef<str>) -> MainWorker {
-        let options = WorkerOptionsBuilder::default()
-            .main_module(Some(file.as_ref()))
-            .permissions(Permissions::allow_all())
-            .build()
-            .unwrap();
+        create_test_worker_with(|options| options.main_module(Some(file.as_ref())))
+    }
+
+    /// A worker with all the permissions and the options set by `f`.
+    pub fn create_test_worker_with(
+        f: impl FnOnce(WorkerOptionsBuilder) -> WorkerOptionsBuilder,
+    ) -> MainWorker {
+        let options = WorkerOptionsBuilder::default().permissions(Permissions::allow_all());
+        let options = f(options).build().unwrap();
 
         MainWorker::bootstrap_from_options(options, vec![])
     }
+
+    /// A web worker with all the permissions and the heap limit it would
+    /// inherit from its parent, not bootstrapped.
+    pub fn create_test_web_worker(max_heap_size: Option<usize>) -> WebWorker {
+        let options = WebWorkerOptions {
+            bootstrap: BootstrapOptions::default(),
+            unsafely_ignore_certificate_errors: None,
+            root_cert_store: None,
+            seed: None,
+            module_loader: Rc::new(FsModuleLoader),
+            create_web_worker_cb: Arc::new(|_| panic!("Web workers are not supported")),
+            preload_module_cb: Arc::new(|_| panic!("Web workers are not supported")),
+            format_js_error_fn: None,
+            worker_type: WebWorkerType::Module,
+            maybe_inspector_server: None,
+            get_error_class_fn: None,
+            blob_store: BlobStore::default(),
+            broadcast_channel: InMemoryBroadcastChannel::default(),
+            shared_array_buffer_store: None,
+            compiled_wasm_module_store: None,
+            stdio: Default::default(),
+            permissions: Permissions::allow_all(),
+            startup_snapshot: None,
+            initial_heap_size: 0,
+            max_heap_size,
+        };
+        let main_module = resolve_url("file:///worker.js").unwrap();
+        let (worker, _) = WebWorker::from_options(
+            "test".to_string(),
+            main_module,
+            WorkerId::default(),
+            options,
+            vec![],
+        );
+        worker
+    }
 }
@@ -0,0 +1,425 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+//! Execution time limits of a worker, enforced by a watchdog thread which
+//! terminates the isolate once a limit is exceeded, and its heap limit.
+
+use deno_core::error::AnyError;
+use deno_core::v8;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::watch;
+
+/// Why the execution of a worker was terminated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerTerminated {
+    /// a call ran longer than the wall-clock limit
+    WallClockLimit(Duration),
+    /// a call used more CPU time than the limit
+    CpuTimeLimit(Duration),
+    /// [TerminationHandle::terminate] was called
+    Requested,
+}
+
+impl fmt::Display for WorkerTerminated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkerTerminated::WallClockLimit(limit) => {
+                write!(
+                    f,
+                    "Execution terminated: wall-clock limit of {:?} exceeded.",
+                    limit
+                )
+            }
+            WorkerTerminated::CpuTimeLimit(limit) => {
+                write!(
+                    f,
+                    "Execution terminated: CPU time limit of {:?} exceeded.",
+                    limit
+                )
+            }
+            WorkerTerminated::Requested => write!(f, "Execution terminated."),
+        }
+    }
+}
+
+impl std::error::Error for WorkerTerminated {}
+
+/// The worker was terminated as its heap got close to the limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapLimitExceeded {
+    /// the max heap size of the worker, in bytes
+    pub max_heap_size: usize,
+}
+
+impl fmt::Display for HeapLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Execution terminated: heap limit of {} bytes exceeded.",
+            self.max_heap_size
+        )
+    }
+}
+
+impl std::error::Error for HeapLimitExceeded {}
+
+/// How far the heap limit is raised when it's reached, just enough for the
+/// isolate to unwind once it's terminated.
+const HEAP_LIMIT_HEADROOM: usize = 16 * 1024 * 1024;
+
+/// The V8 params which apply the heap limits, if there's a max heap size.
+pub(crate) fn heap_create_params(
+    initial_heap_size: usize,
+    max_heap_size: Option<usize>,
+) -> Option<v8::CreateParams> {
+    max_heap_size.map(|max| v8::CreateParams::default().heap_limits(initial_heap_size, max))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reason {
+    Terminated(WorkerTerminated),
+    HeapLimit(HeapLimitExceeded),
+}
+
+impl Reason {
+    /// Whether the worker can't be used anymore.
+    fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Reason::Terminated(WorkerTerminated::Requested) | Reason::HeapLimit(_)
+        )
+    }
+
+    fn into_error(self) -> AnyError {
+        match self {
+            Reason::Terminated(e) => e.into(),
+            Reason::HeapLimit(e) => e.into(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct WatchState {
+    /// nesting depth of the limited calls, only the outermost one is timed
+    depth: usize,
+    current: Option<Run>,
+    /// the reason the current call was terminated, reset for the next call
+    /// unless it's fatal
+    reason: Option<Reason>,
+    shutdown: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Run {
+    started: Instant,
+    /// the CPU clock of the thread running the call, and its time when the
+    /// call started
+    cpu_started: Option<(CpuClock, Duration)>,
+}
+
+struct Shared {
+    state: Mutex<WatchState>,
+    cvar: Condvar,
+    isolate_handle: v8::IsolateHandle,
+    /// signaled on each termination, for the calls waiting on pending ops
+    terminated_tx: watch::Sender<()>,
+    terminated_rx: watch::Receiver<()>,
+}
+
+impl Shared {
+    /// Must be called with the state locked, so that the reason is seen by the
+    /// call which gets terminated. A fatal reason is kept.
+    fn terminate(&self, state: &mut WatchState, reason: Reason) {
+        if !state.reason.map_or(false, |r| r.is_fatal()) {
+            state.reason = Some(reason);
+        }
+        state.current = None;
+        self.isolate_handle.terminate_execution();
+        // the receiver in `self` keeps the channel open
+        let _ = self.terminated_tx.send(());
+    }
+}
+
+/// A thread-safe handle to terminate the execution of a worker, e.g. from
+/// another thread. A terminated worker fails all the later calls, like the one
+/// which exceeded its heap limit.
+#[derive(Clone)]
+pub struct TerminationHandle(Arc<Shared>);
+
+impl TerminationHandle {
+    pub fn terminate(&self) {
+        let mut state = self.0.state.lock().unwrap();
+        self.0
+            .terminate(&mut state, Reason::Terminated(WorkerTerminated::Requested));
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        let state = self.0.state.lock().unwrap();
+        state.reason.map_or(false, |r| r.is_fatal())
+    }
+}
+
+/// Times the calls into a worker and terminates the ones which exceed the
+/// limits. The CPU time is the one of the thread running the call, on the
+/// platforms without per-thread CPU clocks the wall-clock time is used.
+pub(crate) struct Watchdog {
+    shared: Arc<Shared>,
+    cpu_time_limit: Option<Duration>,
+}
+
+impl Watchdog {
+    pub fn new(
+        isolate_handle: v8::IsolateHandle,
+        wall_clock_limit: Option<Duration>,
+        cpu_time_limit: Option<Duration>,
+    ) -> Self {
+        let (terminated_tx, terminated_rx) = watch::channel(());
+        let shared = Arc::new(Shared {
+            state: Mutex::new(WatchState::default()),
+            cvar: Condvar::new(),
+            isolate_handle,
+            terminated_tx,
+            terminated_rx,
+        });
+        if wall_clock_limit.is_some() || cpu_time_limit.is_some() {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("worker-watchdog".to_string())
+                .spawn(move || watch(shared, wall_clock_limit, cpu_time_limit))
+                .expect("Failed to spawn the watchdog thread");
+        }
+        Self {
+            shared,
+            cpu_time_limit,
+        }
+    }
+
+    pub fn termination_handle(&self) -> TerminationHandle {
+        TerminationHandle(self.shared.clone())
+    }
+
+    /// The callback to add with `JsRuntime::add_near_heap_limit_callback`,
+    /// it terminates the worker and raises the limit once by
+    /// [HEAP_LIMIT_HEADROOM], so that V8 doesn't abort the process before the
+    /// termination. The limit is never raised again.
+    pub fn near_heap_limit_callback(
+        &self,
+        max_heap_size: usize,
+    ) -> impl FnMut(usize, usize) -> usize {
+        let shared = self.shared.clone();
+        let mut raised = false;
+        move |current_limit, _initial_limit| {
+            let mut state = shared.state.lock().unwrap();
+            let reason = Reason::HeapLimit(HeapLimitExceeded { max_heap_size });
+            shared.terminate(&mut state, reason);
+            if raised {
+                return current_limit;
+            }
+            raised = true;
+            current_limit + HEAP_LIMIT_HEADROOM
+        }
+    }
+
+    /// Start timing a call, the limits are reset unless it's nested in another
+    /// call. Fails if the worker was terminated with a [TerminationHandle] or
+    /// exceeded its heap limit.
+    pub fn start(&self) -> Result<RunGuard, AnyError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(reason) = state.reason.filter(|r| r.is_fatal()) {
+            return Err(reason.into_error());
+        }
+        if state.depth == 0 {
+            state.reason = None;
+            // the worker could be moved to another thread between the calls,
+            // e.g. by a multi-threaded runtime, so the clock is taken per call
+            let cpu_started = self.cpu_time_limit.map(|_| {
+                let clock = CpuClock::current_thread();
+                (clock, clock.now())
+            });
+            state.current = Some(Run {
+                started: Instant::now(),
+                cpu_started,
+            });
+            self.shared.cvar.notify_one();
+        }
+        state.depth += 1;
+        Ok(RunGuard {
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.shutdown = true;
+        self.shared.cvar.notify_one();
+    }
+}
+
+/// A call being timed, it stops being timed when dropped, e.g. when the future
+/// of the call is dropped.
+pub(crate) struct RunGuard {
+    shared: Arc<Shared>,
+}
+
+impl RunGuard {
+    /// Replace the result of the call with a [WorkerTerminated] or a
+    /// [HeapLimitExceeded] error if it was terminated, whatever the isolate
+    /// returned after the termination.
+    pub fn finish<T>(self, result: Result<T, AnyError>) -> Result<T, AnyError> {
+        let reason = self.shared.state.lock().unwrap().reason;
+        match reason {
+            Some(reason) => Err(reason.into_error()),
+            None => result,
+        }
+    }
+
+    /// Run the future of the call until it's done or the worker is
+    /// terminated. The termination only interrupts the running javascript, so
+    /// a call waiting on a pending op, e.g. a timer or a fetch, would
+    /// otherwise outlive the limits until the op resolves.
+    pub async fn run<T>(
+        self,
+        fut: impl Future<Output = Result<T, AnyError>>,
+    ) -> Result<T, AnyError> {
+        let result = tokio::select! {
+            biased;
+            result = fut => result,
+            err = self.terminated() => Err(err),
+        };
+        self.finish(result)
+    }
+
+    async fn terminated(&self) -> AnyError {
+        let mut terminated_rx = self.shared.terminated_rx.clone();
+        loop {
+            let reason = self.shared.state.lock().unwrap().reason;
+            if let Some(reason) = reason {
+                return reason.into_error();
+            }
+            // a termination after the check above is seen as a change
+            let _ = terminated_rx.changed().await;
+        }
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.depth -= 1;
+        if state.depth == 0 {
+            state.current = None;
+            if state.reason.map_or(false, |r| !r.is_fatal()) {
+                // keep the isolate usable for the next calls
+                self.shared.isolate_handle.cancel_terminate_execution();
+            }
+        }
+    }
+}
+
+fn watch(
+    shared: Arc<Shared>,
+    wall_clock_limit: Option<Duration>,
+    cpu_time_limit: Option<Duration>,
+) {
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        if state.shutdown {
+            return;
+        }
+        let run = match state.current {
+            Some(run) => run,
+            None => {
+                state = shared.cvar.wait(state).unwrap();
+                continue;
+            }
+        };
+
+        let mut timeout = Duration::MAX;
+        if let Some(limit) = wall_clock_limit {
+            let elapsed = run.started.elapsed();
+            if elapsed >= limit {
+                let reason = Reason::Terminated(WorkerTerminated::WallClockLimit(limit));
+                shared.terminate(&mut state, reason);
+                continue;
+            }
+            timeout = timeout.min(limit - elapsed);
+        }
+        if let (Some(limit), Some((clock, cpu_started))) = (cpu_time_limit, run.cpu_started) {
+            let used = clock.now().saturating_sub(cpu_started);
+            if used >= limit {
+                let reason = Reason::Terminated(WorkerTerminated::CpuTimeLimit(limit));
+                shared.terminate(&mut state, reason);
+                continue;
+            }
+            // a thread can't use more CPU time than the wall-clock time
+            timeout = timeout.min(limit - used);
+        }
+        state = shared.cvar.wait_timeout(state, timeout).unwrap().0;
+    }
+}
+
+/// The CPU clock of a thread, readable from the other threads.
+#[derive(Clone, Copy)]
+enum CpuClock {
+    #[cfg(target_os = "linux")]
+    Thread(libc::clockid_t),
+    /// wall-clock time, for the platforms without per-thread CPU clocks
+    Wall(Instant),
+}
+
+impl CpuClock {
+    fn current_thread() -> Self {
+        #[cfg(target_os = "linux")]
+        {
+            let mut clock_id: libc::clockid_t = 0;
+            // SAFETY: pthread_self is always a valid thread
+            if unsafe { libc::pthread_getcpuclockid(libc::pthread_self(), &mut clock_id) } == 0 {
+                return CpuClock::Thread(clock_id);
+            }
+        }
+        CpuClock::Wall(Instant::now())
+    }
+
+    fn now(&self) -> Duration {
+        match self {
+            #[cfg(target_os = "linux")]
+            CpuClock::Thread(clock_id) => {
+                let mut ts = libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 0,
+                };
+                // SAFETY: ts is a valid timespec to write into
+                if unsafe { libc::clock_gettime(*clock_id, &mut ts) } != 0 {
+                    return Duration::ZERO;
+                }
+                Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+            }
+            CpuClock::Wall(start) => start.elapsed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deno_core::JsRuntime;
+
+    #[test]
+    fn near_heap_limit_callback_should_raise_the_limit_once() {
+        let mut runtime = JsRuntime::new(Default::default());
+        let watchdog = Watchdog::new(runtime.v8_isolate().thread_safe_handle(), None, None);
+        let mut callback = watchdog.near_heap_limit_callback(64 * 1024 * 1024);
+        let limit = callback(64 * 1024 * 1024, 0);
+        assert_eq!(limit, 64 * 1024 * 1024 + HEAP_LIMIT_HEADROOM);
+        assert!(watchdog.termination_handle().is_terminated());
+        assert_eq!(callback(limit, 0), limit);
+    }
+}
@@ -12,6 +12,7 @@ use crate::web_worker::WebWorkerType;
 use crate::web_worker::WorkerControlEvent;
 use crate::web_worker::WorkerId;
 use crate::worker::FormatJsErrorFn;
+use deno_core::error::generic_error;
 use deno_core::error::AnyError;
 use deno_core::futures::future::LocalFutureObj;
 use deno_core::op;
@@ -36,6 +37,11 @@ pub struct CreateWebWorkerArgs {
     pub permissions: Permissions,
     pub main_module: ModuleSpecifier,
     pub worker_type: WebWorkerType,
+    /// heap limits of the parent worker, to be put into the
+    /// [WebWorkerOptions](crate::web_worker::WebWorkerOptions), the worker
+    /// fails to start if its max heap size is larger
+    pub initial_heap_size: usize,
+    pub max_heap_size: Option<usize>,
 }
 
 pub type CreateWebWorkerCb =
@@ -54,6 +60,11 @@ pub struct CreateWebWorkerCbHolder(Arc<CreateWebWorkerCb>);
 #[derive(Clone)]
 pub struct FormatJsErrorFnHolder(Option<Arc<FormatJsErrorFn>>);
 
+/// The heap limits the created workers inherit, the initial and the max heap
+/// size.
+#[derive(Clone, Copy)]
+pub struct HeapLimitsHolder(usize, Option<usize>);
+
 /// A holder for callback that can used to preload some modules into a WebWorker
 /// before actual worker code is executed. It's a struct instead of a type
 /// because `GothamState` used in `OpState` overrides
@@ -88,10 +99,29 @@ impl Drop for WorkerThread {
 
 pub type WorkersTable = HashMap<WorkerId, WorkerThread>;
 
+/// Fail if a worker created by the `CreateWebWorkerCb` doesn't apply the max
+/// heap size it inherited, a larger or no limit would let it escape the limit
+/// of its parent.
+pub(crate) fn check_heap_limits(
+    worker: &WebWorker,
+    max_heap_size: Option<usize>,
+) -> Result<(), AnyError> {
+    match (max_heap_size, worker.max_heap_size()) {
+        (Some(max), Some(worker_max)) if worker_max <= max => Ok(()),
+        (Some(max), _) => Err(generic_error(format!(
+            "Worker \"{}\" must be created with the max heap size of its parent, {} bytes.",
+            worker.name, max
+        ))),
+        (None, _) => Ok(()),
+    }
+}
+
 pub fn init(
     create_web_worker_cb: Arc<CreateWebWorkerCb>,
     preload_module_cb: Arc<PreloadModuleCb>,
     format_js_error_fn: Option<Arc<FormatJsErrorFn>>,
+    initial_heap_size: usize,
+    max_heap_size: Option<usize>,
 ) -> Extension {
     Extension::builder()
         .state(move |state| {
@@ -104,6 +134,7 @@ pub fn init(
             state.put::<PreloadModuleCbHolder>(preload_module_cb_holder);
             let format_js_error_fn_holder = FormatJsErrorFnHolder(format_js_error_fn.clone());
             state.put::<FormatJsErrorFnHolder>(format_js_error_fn_holder);
+            state.put::<HeapLimitsHolder>(HeapLimitsHolder(initial_heap_size, max_heap_size));
 
             Ok(())
         })
@@ -165,6 +196,7 @@ fn op_create_worker(state: &mut OpState, args: CreateWorkerArgs) -> Result<Worke
     state.put::<PreloadModuleCbHolder>(preload_module_cb.clone());
     let format_js_error_fn = state.take::<FormatJsErrorFnHolder>();
     state.put::<FormatJsErrorFnHolder>(format_js_error_fn.clone());
+    let HeapLimitsHolder(initial_heap_size, max_heap_size) = *state.borrow::<HeapLimitsHolder>();
     state.put::<WorkerId>(worker_id.next().unwrap());
 
     let module_specifier = deno_core::resolve_url(&specifier)?;
@@ -190,7 +222,13 @@ fn op_create_worker(state: &mut OpState, args: CreateWorkerArgs) -> Result<Worke
             permissions: worker_permissions,
             main_module: module_specifier.clone(),
             worker_type,
+            initial_heap_size,
+            max_heap_size,
         });
+        if let Err(e) = check_heap_limits(&worker, max_heap_size) {
+            handle_sender.send(Err(e)).unwrap();
+            return Ok(());
+        }
 
         // Send thread safe handle from newly created worker to host thread
         handle_sender.send(Ok(external_handle)).unwrap();
@@ -369,3 +407,20 @@ fn op_host_post_message(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::create_test_web_worker;
+
+    #[tokio::test]
+    async fn web_workers_should_apply_the_inherited_heap_limit() {
+        let max_heap_size = 64 * 1024 * 1024;
+        let worker = create_test_web_worker(Some(max_heap_size));
+        assert!(check_heap_limits(&worker, Some(max_heap_size)).is_ok());
+        assert!(check_heap_limits(&worker, Some(max_heap_size / 2)).is_err());
+        let worker = create_test_web_worker(None);
+        assert!(check_heap_limits(&worker, Some(max_heap_size)).is_err());
+        assert!(check_heap_limits(&worker, None).is_ok());
+    }
+}
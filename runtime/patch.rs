@@ -66,6 +66,10 @@ impl Default for WorkerOptions {
             permissions: Permissions::default(),
             startup_snapshot: None,
             runtime_options_callback: None,
+            wall_clock_limit: None,
+            cpu_time_limit: None,
+            initial_heap_size: 0,
+            max_heap_size: None,
         }
     }
 }
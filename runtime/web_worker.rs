@@ -3,6 +3,8 @@ use crate::StartSnapshot;
 use crate::colors;
 use crate::inspector_server::InspectorServer;
 use crate::js;
+use crate::limits::heap_create_params;
+use crate::limits::Watchdog;
 use crate::ops;
 use crate::ops::io::Stdio;
 use crate::permissions::Permissions;
@@ -307,6 +309,8 @@ pub struct WebWorker {
     pub worker_type: WebWorkerType,
     pub main_module: ModuleSpecifier,
     poll_for_messages_fn: Option<v8::Global<v8::Value>>,
+    watchdog: Watchdog,
+    max_heap_size: Option<usize>,
 }
 
 #[derive(Builder, Clone)]
@@ -332,6 +336,12 @@ pub struct WebWorkerOptions {
 
     pub permissions: Permissions,
     pub startup_snapshot: Option<StartSnapshot>,
+    /// Heap limits like the ones of `WorkerOptions`, usually inherited from
+    /// the parent worker through `CreateWebWorkerArgs`.
+    #[builder(default)]
+    pub initial_heap_size: usize,
+    #[builder(default)]
+    pub max_heap_size: Option<usize>,
 }
 
 impl WebWorker {
@@ -399,6 +409,8 @@ impl WebWorker {
                 options.create_web_worker_cb.clone(),
                 options.preload_module_cb.clone(),
                 options.format_js_error_fn.clone(),
+                options.initial_heap_size,
+                options.max_heap_size,
             ),
             // Extensions providing Deno.* features
             ops::fs_events::init(),
@@ -440,10 +452,17 @@ impl WebWorker {
             get_error_class_fn: options.get_error_class_fn,
             shared_array_buffer_store: options.shared_array_buffer_store.clone(),
             compiled_wasm_module_store: options.compiled_wasm_module_store.clone(),
+            create_params: heap_create_params(options.initial_heap_size, options.max_heap_size),
             extensions,
             ..Default::default()
         });
 
+        let watchdog = Watchdog::new(js_runtime.v8_isolate().thread_safe_handle(), None, None);
+        if let Some(max_heap_size) = options.max_heap_size {
+            js_runtime
+                .add_near_heap_limit_callback(watchdog.near_heap_limit_callback(max_heap_size));
+        }
+
         if let Some(server) = options.maybe_inspector_server.clone() {
             server.register_inspector(main_module.to_string(), &mut js_runtime, false);
         }
@@ -467,6 +486,8 @@ impl WebWorker {
                 worker_type: options.worker_type,
                 main_module,
                 poll_for_messages_fn: None,
+                watchdog,
+                max_heap_size: options.max_heap_size,
             },
             external_handle,
         )
@@ -497,9 +518,16 @@ impl WebWorker {
         self.poll_for_messages_fn = Some(poll_for_messages_fn);
     }
 
+    /// The max heap size the isolate of the worker was created with.
+    pub fn max_heap_size(&self) -> Option<usize> {
+        self.max_heap_size
+    }
+
     /// See [JsRuntime::execute_script](deno_core::JsRuntime::execute_script)
     pub fn execute_script(&mut self, name: &str, source_code: &str) -> Result<(), AnyError> {
-        self.js_runtime.execute_script(name, source_code)?;
+        let guard = self.watchdog.start()?;
+        let result = self.js_runtime.execute_script(name, source_code);
+        guard.finish(result)?;
         Ok(())
     }
 
@@ -532,6 +560,11 @@ impl WebWorker {
         module_specifier: &ModuleSpecifier,
     ) -> Result<(), AnyError> {
         let id = self.preload_side_module(module_specifier).await?;
+        let guard = self.watchdog.start()?;
+        guard.run(self.evaluate_side_module(id)).await
+    }
+
+    async fn evaluate_side_module(&mut self, id: ModuleId) -> Result<(), AnyError> {
         let mut receiver = self.js_runtime.mod_evaluate(id);
         tokio::select! {
           biased;
@@ -552,6 +585,11 @@ impl WebWorker {
     ///
     /// This module will have "import.meta.main" equal to true.
     pub async fn execute_main_module(&mut self, id: ModuleId) -> Result<(), AnyError> {
+        let guard = self.watchdog.start()?;
+        guard.run(self.evaluate_main_module(id)).await
+    }
+
+    async fn evaluate_main_module(&mut self, id: ModuleId) -> Result<(), AnyError> {
         let mut receiver = self.js_runtime.mod_evaluate(id);
         tokio::select! {
           biased;
@@ -604,7 +642,10 @@ impl WebWorker {
     }
 
     pub async fn run_event_loop(&mut self, wait_for_inspector: bool) -> Result<(), AnyError> {
-        poll_fn(|cx| self.poll_event_loop(cx, wait_for_inspector)).await
+        let guard = self.watchdog.start()?;
+        guard
+            .run(poll_fn(|cx| self.poll_event_loop(cx, wait_for_inspector)))
+            .await
     }
 
     // Starts polling for messages from worker host from JavaScript.
@@ -710,3 +751,28 @@ pub fn run_web_worker(
     };
     run_local(fut)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::limits::HeapLimitExceeded;
+    use crate::test_util::*;
+
+    #[tokio::test]
+    async fn heap_limit_should_terminate_the_web_worker() {
+        let max_heap_size = 64 * 1024 * 1024;
+        let mut worker = create_test_web_worker(Some(max_heap_size));
+        assert_eq!(worker.max_heap_size(), Some(max_heap_size));
+        let err = worker
+            .execute_script(
+                "oom.js",
+                "const a = []; while (true) { a.push(new Array(1e6).fill(1)); }",
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<HeapLimitExceeded>(),
+            Some(&HeapLimitExceeded { max_heap_size })
+        );
+        let err = worker.execute_script("ok.js", "1 + 1").unwrap_err();
+        assert!(err.downcast_ref::<HeapLimitExceeded>().is_some());
+    }
+}
@@ -2,6 +2,9 @@
 
 use crate::inspector_server::InspectorServer;
 use crate::js;
+use crate::limits::heap_create_params;
+use crate::limits::TerminationHandle;
+use crate::limits::Watchdog;
 use crate::ops;
 use crate::ops::io::Stdio;
 use crate::permissions::Permissions;
@@ -34,6 +37,7 @@ use std::sync::atomic::Ordering::Relaxed;
 use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Duration;
 
 use crate::StartSnapshot;
 use derive_builder::Builder;
@@ -65,6 +69,7 @@ pub struct MainWorker {
     main_module: Option<ModuleSpecifier>,
     should_break_on_first_statement: bool,
     exit_code: ExitCode,
+    watchdog: Watchdog,
 }
 
 pub type RuntimeOptionsCallback = Rc<dyn Fn(RuntimeOptions) -> RuntimeOptions>;
@@ -100,6 +105,17 @@ pub struct WorkerOptions {
     pub permissions: Permissions,
     pub startup_snapshot: Option<StartSnapshot>,
     pub runtime_options_callback: Option<RuntimeOptionsCallback>,
+    /// Terminate a call into the worker, e.g. `execute_main_module` or
+    /// `run_event_loop`, which runs longer than this.
+    pub wall_clock_limit: Option<Duration>,
+    /// Terminate a call into the worker which uses more CPU time than this.
+    pub cpu_time_limit: Option<Duration>,
+    /// Initial heap size of the isolate in bytes, used with `max_heap_size`.
+    pub initial_heap_size: usize,
+    /// Terminate the worker when its heap gets close to this size in bytes,
+    /// instead of running out of memory. The web workers it creates get the
+    /// same heap limits.
+    pub max_heap_size: Option<usize>,
 }
 
 impl WorkerOptionsBuilder {
@@ -168,6 +184,8 @@ impl MainWorker {
                 options.create_web_worker_cb.clone(),
                 options.web_worker_preload_module_cb.clone(),
                 options.format_js_error_fn.clone(),
+                options.initial_heap_size,
+                options.max_heap_size,
             ),
             ops::spawn::init(),
             ops::fs_events::init(),
@@ -211,6 +229,7 @@ impl MainWorker {
             get_error_class_fn: options.get_error_class_fn,
             shared_array_buffer_store: options.shared_array_buffer_store.clone(),
             compiled_wasm_module_store: options.compiled_wasm_module_store.clone(),
+            create_params: heap_create_params(options.initial_heap_size, options.max_heap_size),
             extensions,
             ..Default::default()
         };
@@ -222,6 +241,15 @@ impl MainWorker {
         };
 
         let mut js_runtime = JsRuntime::new(opts);
+        let watchdog = Watchdog::new(
+            js_runtime.v8_isolate().thread_safe_handle(),
+            options.wall_clock_limit,
+            options.cpu_time_limit,
+        );
+        if let Some(max_heap_size) = options.max_heap_size {
+            js_runtime
+                .add_near_heap_limit_callback(watchdog.near_heap_limit_callback(max_heap_size));
+        }
 
         if let Some(main) = main_module.as_ref() {
             if let Some(server) = options.maybe_inspector_server.clone() {
@@ -238,6 +266,7 @@ impl MainWorker {
             main_module,
             should_break_on_first_statement: options.should_break_on_first_statement,
             exit_code,
+            watchdog,
         }
     }
 
@@ -249,7 +278,9 @@ impl MainWorker {
 
     /// See [JsRuntime::execute_script](deno_core::JsRuntime::execute_script)
     pub fn execute_script(&mut self, script_name: &str, source_code: &str) -> Result<(), AnyError> {
-        self.js_runtime.execute_script(script_name, source_code)?;
+        let guard = self.watchdog.start()?;
+        let result = self.js_runtime.execute_script(script_name, source_code);
+        guard.finish(result)?;
         Ok(())
     }
 
@@ -275,6 +306,11 @@ impl MainWorker {
 
     /// Executes specified JavaScript module.
     pub async fn evaluate_module(&mut self, id: ModuleId) -> Result<(), AnyError> {
+        let guard = self.watchdog.start()?;
+        guard.run(self.evaluate_module_inner(id)).await
+    }
+
+    async fn evaluate_module_inner(&mut self, id: ModuleId) -> Result<(), AnyError> {
         self.wait_for_inspector_session();
         let mut receiver = self.js_runtime.mod_evaluate(id);
         tokio::select! {
@@ -339,16 +375,41 @@ impl MainWorker {
         inspector.create_local_session()
     }
 
+    /// Each poll is limited like a call, so that a host driving the event
+    /// loop itself can't hang on a script.
     pub fn poll_event_loop(
         &mut self,
         cx: &mut Context,
         wait_for_inspector: bool,
     ) -> Poll<Result<(), AnyError>> {
-        self.js_runtime.poll_event_loop(cx, wait_for_inspector)
+        let guard = match self.watchdog.start() {
+            Ok(guard) => guard,
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+        match self.js_runtime.poll_event_loop(cx, wait_for_inspector) {
+            Poll::Ready(result) => Poll::Ready(guard.finish(result)),
+            Poll::Pending => match guard.finish(Ok(())) {
+                Ok(()) => Poll::Pending,
+                Err(e) => Poll::Ready(Err(e)),
+            },
+        }
     }
 
     pub async fn run_event_loop(&mut self, wait_for_inspector: bool) -> Result<(), AnyError> {
-        self.js_runtime.run_event_loop(wait_for_inspector).await
+        let guard = self.watchdog.start()?;
+        guard
+            .run(self.js_runtime.run_event_loop(wait_for_inspector))
+            .await
+    }
+
+    /// A handle to terminate the worker from any thread, the running call and
+    /// all the later ones fail with a
+    /// [WorkerTerminated](crate::limits::WorkerTerminated) error.
+    ///
+    /// A worker which exceeded its `max_heap_size` is terminated the same way,
+    /// with a [HeapLimitExceeded](crate::limits::HeapLimitExceeded) error.
+    pub fn termination_handle(&self) -> TerminationHandle {
+        self.watchdog.termination_handle()
     }
 
     /// A utility function that runs provided future concurrently with the event loop.
@@ -405,13 +466,15 @@ impl MainWorker {
     /// indicating if the event was prevented and thus event loop should continue
     /// running.
     pub fn dispatch_beforeunload_event(&mut self, script_name: &str) -> Result<bool, AnyError> {
-        let value = self.js_runtime.execute_script(
+        let guard = self.watchdog.start()?;
+        let result = self.js_runtime.execute_script(
             script_name,
             // NOTE(@bartlomieju): not using `globalThis` here, because user might delete
             // it. Instead we're using global `dispatchEvent` function which will
             // used a saved reference to global scope.
             "dispatchEvent(new Event('beforeunload', { cancelable: true }));",
-        )?;
+        );
+        let value = guard.finish(result)?;
         let local_value = value.open(&mut self.js_runtime.handle_scope());
         Ok(local_value.is_false())
     }
@@ -419,7 +482,12 @@ impl MainWorker {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::limits::HeapLimitExceeded;
+    use crate::limits::WorkerTerminated;
     use crate::test_util::*;
+    use deno_core::futures::future::poll_fn;
+    use std::time::Instant;
 
     #[tokio::test]
     async fn execute_mod_esm_imports_a() {
@@ -464,4 +532,124 @@ mod tests {
         let result = worker.execute_main_module().await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn wall_clock_limit_should_terminate_execution() {
+        let mut worker =
+            create_test_worker_with(|o| o.wall_clock_limit(Some(Duration::from_millis(100))));
+        let start = Instant::now();
+        let err = worker
+            .execute_script("loop.js", "while (true) {}")
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<WorkerTerminated>(),
+            Some(&WorkerTerminated::WallClockLimit(Duration::from_millis(
+                100
+            )))
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // the limits apply to each call
+        worker.execute_script("ok.js", "1 + 1").unwrap();
+        worker
+            .execute_script("timer.js", "setTimeout(() => { while (true) {} }, 0)")
+            .unwrap();
+        let err = worker.run_event_loop(false).await.unwrap_err();
+        assert!(err.downcast_ref::<WorkerTerminated>().is_some());
+    }
+
+    #[tokio::test]
+    async fn wall_clock_limit_should_not_wait_for_pending_ops() {
+        let limit = Duration::from_millis(100);
+        let mut worker = create_test_worker_with(|o| o.wall_clock_limit(Some(limit)));
+        worker
+            .execute_script("timer.js", "setTimeout(() => {}, 60000)")
+            .unwrap();
+        let start = Instant::now();
+        let err = worker.run_event_loop(false).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<WorkerTerminated>(),
+            Some(&WorkerTerminated::WallClockLimit(limit))
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn limits_should_apply_to_polls_and_beforeunload() {
+        let limit = Duration::from_millis(100);
+        let mut worker = create_test_worker_with(|o| o.wall_clock_limit(Some(limit)));
+        worker
+            .execute_script("timer.js", "setTimeout(() => { while (true) {} }, 0)")
+            .unwrap();
+        let err = poll_fn(|cx| worker.poll_event_loop(cx, false))
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<WorkerTerminated>().is_some());
+
+        worker
+            .execute_script(
+                "beforeunload.js",
+                "addEventListener('beforeunload', () => { while (true) {} })",
+            )
+            .unwrap();
+        let err = worker
+            .dispatch_beforeunload_event("beforeunload.js")
+            .unwrap_err();
+        assert!(err.downcast_ref::<WorkerTerminated>().is_some());
+    }
+
+    #[tokio::test]
+    async fn cpu_time_limit_should_terminate_execution() {
+        let mut worker =
+            create_test_worker_with(|o| o.cpu_time_limit(Some(Duration::from_millis(100))));
+        let err = worker
+            .execute_script("loop.js", "while (true) {}")
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<WorkerTerminated>(),
+            Some(&WorkerTerminated::CpuTimeLimit(Duration::from_millis(100)))
+        );
+    }
+
+    #[tokio::test]
+    async fn termination_handle_should_terminate_execution() {
+        let mut worker = create_test_worker_with(|o| o);
+        let handle = worker.termination_handle();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle.terminate();
+        });
+        let err = worker
+            .execute_script("loop.js", "while (true) {}")
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<WorkerTerminated>(),
+            Some(&WorkerTerminated::Requested)
+        );
+        assert!(worker.termination_handle().is_terminated());
+        assert!(worker.execute_script("ok.js", "1 + 1").is_err());
+    }
+
+    #[tokio::test]
+    async fn heap_limit_should_terminate_only_the_worker() {
+        let max_heap_size = 64 * 1024 * 1024;
+        let mut worker = create_test_worker_with(|o| o.max_heap_size(Some(max_heap_size)));
+        let err = worker
+            .execute_script(
+                "oom.js",
+                "const a = []; while (true) { a.push(new Array(1e6).fill(1)); }",
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<HeapLimitExceeded>(),
+            Some(&HeapLimitExceeded { max_heap_size })
+        );
+        assert!(worker.termination_handle().is_terminated());
+        let err = worker.execute_script("ok.js", "1 + 1").unwrap_err();
+        assert!(err.downcast_ref::<HeapLimitExceeded>().is_some());
+
+        // the other workers are fine
+        let mut worker = create_test_worker_with(|o| o);
+        worker.execute_script("ok.js", "1 + 1").unwrap();
+    }
 }
@@ -1,11 +1,50 @@
-use deno_broadcast_channel::InMemoryBroadcastChannel;
-use deno_core::{anyhow::Result, Extension, JsRuntime, RuntimeOptions};
-use deno_web::BlobStore;
-use std::{env, path::PathBuf};
+use deno_core::{
+    anyhow::{bail, Context, Result},
+    futures, Extension, JsRuntime, RuntimeOptions,
+};
+use std::path::{Path, PathBuf};
 
-use crate::permissions::Permissions;
+use crate::{
+    encode,
+    permissions::{get_error_class, PermissionPolicy, Permissions, PERMISSIONS_JS},
+    BuiltinExtension, Compression, SnapshotContents,
+};
 
-const JS_PATHS: &[&str] = &["js/**/*.js"];
+macro_rules! runtime_js {
+    ($($name:literal),* $(,)?) => {
+        &[$((concat!("js/", $name), include_str!(concat!("../js/", $name)))),*]
+    };
+}
+
+/// The runtime scripts under `js/`, embedded so that the builder works without
+/// the sources of this crate, e.g. in a deployed binary.
+const RUNTIME_JS: &[(&str, &str)] = runtime_js![
+    "01_build.js",
+    "01_errors.js",
+    "01_version.js",
+    "01_web_util.js",
+    "06_util.js",
+    "10_permissions.js",
+    "11_workers.js",
+    "12_io.js",
+    "13_buffer.js",
+    "30_fs.js",
+    "30_os.js",
+    "40_diagnostics.js",
+    "40_files.js",
+    "40_fs_events.js",
+    "40_http.js",
+    "40_process.js",
+    "40_read_file.js",
+    "40_signals.js",
+    "40_spawn.js",
+    "40_testing.js",
+    "40_tty.js",
+    "40_write_file.js",
+    "41_prompt.js",
+    "90_deno_ns.js",
+    "99_main.js",
+];
 
 pub fn create_snapshot_with_main_module(
     exts: Vec<Extension>,
@@ -25,78 +64,355 @@ pub fn _create_snapshot(
     code: Option<String>,
     build: bool,
 ) -> Result<Vec<u8>> {
-    // Order matters!
-    let mut extensions = vec![
-        deno_webidl::init(),
-        deno_console::init(),
-        deno_url::init(),
-        deno_web::init::<Permissions>(BlobStore::default(), Default::default()),
-        deno_fetch::init::<Permissions>(Default::default()),
-        deno_websocket::init::<Permissions>("".to_owned(), None, None),
-        deno_webstorage::init(None),
-        deno_crypto::init(None),
-        #[cfg(feature = "build_webgpu")]
-        deno_webgpu::init(false),
-        deno_broadcast_channel::init(InMemoryBroadcastChannel::default(), false),
-        deno_tls::init(),
-        deno_net::init::<Permissions>(
-            None, false, // No --unstable.
-            None,
-        ),
-        deno_http::init(),
-    ];
-    extensions.extend(exts);
-
-    let rt = JsRuntime::new(RuntimeOptions {
-        will_snapshot: true,
-        extensions,
-        ..Default::default()
-    });
-    _gen_snapshot(rt, files, code, build)
+    let mut builder = SnapshotBuilder::new()
+        .extensions(exts)
+        .files(files.iter().cloned())
+        .rerun_if_changed(build);
+    builder.code = code;
+    builder.build()
 }
 
-pub fn get_js_files(base_dir: &str, paths: &[&str]) -> Vec<PathBuf> {
-    let mut files: Vec<_> = paths
-        .iter()
-        .flat_map(|p| {
-            glob::glob(format!("{}/{}", base_dir, p).as_str())
-                .unwrap()
-                .filter_map(Result::ok)
-                .collect::<Vec<_>>()
-        })
-        .collect();
-
-    files.sort();
-    files
+/// A script executed while building the snapshot.
+enum Script {
+    File(PathBuf),
+    Source { name: String, code: String },
 }
 
-fn _gen_snapshot(
-    mut rt: JsRuntime,
-    files: &[PathBuf],
+/// Choose what goes into a snapshot. By default all the [BuiltinExtension]s
+/// and the runtime scripts under `js/` of this crate are included.
+///
+/// The builder could be used at runtime as well as in build scripts: the
+/// runtime scripts are embedded, the files could be read from an explicit base
+/// directory, the scripts could be in-memory sources, and errors are returned
+/// instead of panicking.
+///
+/// ```ignore
+/// // a slim snapshot with console and url only
+/// let data = SnapshotBuilder::new()
+///     .builtins([BuiltinExtension::Webidl, BuiltinExtension::Console, BuiltinExtension::Url])
+///     .runtime_js(false)
+///     .source("tenant.js", "globalThis.tenant = 'acme';")
+///     .build()?;
+/// ```
+pub struct SnapshotBuilder {
+    builtins: Vec<BuiltinExtension>,
+    extensions: Vec<Extension>,
+    runtime_js: bool,
+    base_dir: Option<PathBuf>,
+    scripts: Vec<Script>,
     code: Option<String>,
-    build: bool,
-) -> Result<Vec<u8>> {
-    let base_dir = env!("CARGO_MANIFEST_DIR");
-    // let display_root = Path::new(base_dir).parent().unwrap();
-    let mut all_files = get_js_files(base_dir, JS_PATHS);
-    all_files.extend_from_slice(files);
-    for file in all_files {
-        if build {
-            println!("cargo:rerun-if-changed={}", file.display());
+    rerun_if_changed: bool,
+    compression: Compression,
+    warmup: Option<String>,
+    permissions: PermissionPolicy,
+}
+
+impl Default for SnapshotBuilder {
+    fn default() -> Self {
+        Self {
+            builtins: BuiltinExtension::ALL.to_vec(),
+            extensions: vec![],
+            runtime_js: true,
+            base_dir: None,
+            scripts: vec![],
+            code: None,
+            rerun_if_changed: false,
+            compression: Compression::default(),
+            warmup: None,
+            permissions: PermissionPolicy::default(),
         }
-        // let display_path = file.strip_prefix(display_root).unwrap();
-        let display_path_str = file.display().to_string();
-        rt.execute_script(
-            &("deno:".to_string() + &display_path_str.replace('\\', "/")),
-            &std::fs::read_to_string(&file).unwrap(),
-        )?;
     }
-    if let Some(v) = code {
-        rt.execute_script("deno:main", &v)?;
+}
+
+impl SnapshotBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in extensions put into the snapshot, in this order. An
+    /// extension has to come after its [BuiltinExtension::dependencies].
+    pub fn builtins(mut self, builtins: impl IntoIterator<Item = BuiltinExtension>) -> Self {
+        self.builtins = builtins.into_iter().collect();
+        self
+    }
+
+    /// Custom extensions put into the snapshot after the built-in ones.
+    pub fn extensions(mut self, extensions: impl IntoIterator<Item = Extension>) -> Self {
+        self.extensions.extend(extensions);
+        self
+    }
+
+    /// Whether to run the runtime scripts under `js/` of this crate, which
+    /// need all the built-in extensions.
+    pub fn runtime_js(mut self, runtime_js: bool) -> Self {
+        self.runtime_js = runtime_js;
+        self
+    }
+
+    /// The directory of the relative paths of [Self::files], they're relative
+    /// to the current directory by default.
+    pub fn base_dir(mut self, base_dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = Some(base_dir.into());
+        self
+    }
+
+    /// Scripts executed after the runtime scripts, in the order they're added.
+    pub fn files(mut self, files: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.scripts.extend(files.into_iter().map(Script::File));
+        self
+    }
+
+    /// An in-memory script, executed in the order it's added along with
+    /// [Self::files].
+    pub fn source(mut self, name: impl Into<String>, code: impl Into<String>) -> Self {
+        self.scripts.push(Script::Source {
+            name: name.into(),
+            code: code.into(),
+        });
+        self
+    }
+
+    /// Code executed after the scripts.
+    pub fn code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Print `cargo:rerun-if-changed` for all the files read, for build
+    /// scripts.
+    pub fn rerun_if_changed(mut self, rerun_if_changed: bool) -> Self {
+        self.rerun_if_changed = rerun_if_changed;
+        self
+    }
+
+    /// How the snapshot is compressed, zstd level 7 by default. It's recorded
+    /// in the snapshot so that [crate::decode] picks the right codec.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// A script run right before the snapshot is taken, so that the hot
+    /// functions it calls are compiled into the snapshot. Its side effects
+    /// end up in the snapshot as well, so it shouldn't leave globals behind.
+    pub fn warmup(mut self, code: impl Into<String>) -> Self {
+        self.warmup = Some(code.into());
+        self
     }
 
-    let snapshot = rt.snapshot();
-    let snapshot_slice: &[u8] = &*snapshot;
+    /// How the permission guarded APIs behave while the snapshot is built,
+    /// all the accesses are denied by default.
+    pub fn permissions(mut self, permissions: PermissionPolicy) -> Self {
+        self.permissions = permissions;
+        self
+    }
 
-    Ok(zstd::encode_all(snapshot_slice, 7)?)
+    pub fn build(self) -> Result<Vec<u8>> {
+        // the ops of the built-in extensions, e.g. the timers, need a tokio
+        // runtime. Its drivers run on its own worker, so the event loop is run
+        // by a plain executor which, unlike `Runtime::block_on`, doesn't panic
+        // in an async context but blocks the current thread.
+        let tokio_rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let result = {
+            let _guard = tokio_rt.enter();
+            self.build_snapshot()
+        };
+        // dropping a runtime blocks, which panics in an async context
+        tokio_rt.shutdown_background();
+        result
+    }
+
+    fn build_snapshot(self) -> Result<Vec<u8>> {
+        for (i, ext) in self.builtins.iter().enumerate() {
+            for dep in ext.dependencies() {
+                if !self.builtins[..i].contains(dep) {
+                    bail!(
+                        "Extension \"{}\" requires \"{}\" to be put before it.",
+                        ext,
+                        dep
+                    );
+                }
+            }
+        }
+        if self.runtime_js {
+            if let Some(ext) = BuiltinExtension::ALL
+                .iter()
+                .find(|e| !self.builtins.contains(*e))
+            {
+                bail!(
+                    "The runtime scripts require the extension \"{}\", disable them with runtime_js(false).",
+                    ext
+                );
+            }
+        }
+
+        let mut scripts = Vec::new();
+        if self.runtime_js {
+            scripts.extend(RUNTIME_JS.iter().map(|(name, code)| Script::Source {
+                name: name.to_string(),
+                code: code.to_string(),
+            }));
+        }
+        // before the scripts which could be denied
+        scripts.push(Script::Source {
+            name: "snapshot_permissions.js".to_string(),
+            code: PERMISSIONS_JS.to_string(),
+        });
+        let base_dir = &self.base_dir;
+        scripts.extend(
+            self.scripts
+                .into_iter()
+                .map(|script| match (script, base_dir) {
+                    (Script::File(path), Some(base_dir)) if path.is_relative() => {
+                        Script::File(base_dir.join(path))
+                    }
+                    (script, _) => script,
+                }),
+        );
+
+        let mut contents = SnapshotContents {
+            extensions: self.builtins.iter().map(|e| e.name().to_string()).collect(),
+            ..Default::default()
+        };
+        let permissions = Permissions::new(self.permissions);
+        let state_permissions = permissions.clone();
+        let permissions_ext = Extension::builder()
+            .state(move |state| {
+                state.put(state_permissions.clone());
+                Ok(())
+            })
+            .build();
+        // Order matters!
+        let mut extensions = vec![permissions_ext];
+        extensions.extend(self.builtins.iter().map(|e| e.init()));
+        extensions.extend(self.extensions);
+
+        let mut rt = JsRuntime::new(RuntimeOptions {
+            will_snapshot: true,
+            extensions,
+            get_error_class_fn: Some(&get_error_class),
+            ..Default::default()
+        });
+        for script in scripts {
+            let (name, code) = match script {
+                Script::File(path) => {
+                    if self.rerun_if_changed {
+                        println!("cargo:rerun-if-changed={}", path.display());
+                    }
+                    let code = std::fs::read_to_string(&path)
+                        .with_context(|| format!("Unable to read {}", path.display()))?;
+                    (path.display().to_string(), code)
+                }
+                Script::Source { name, code } => (name, code),
+            };
+            let name = "deno:".to_string() + &name.replace('\\', "/");
+            rt.execute_script(&name, &code)?;
+            permissions.check_unstable_denials()?;
+            contents.scripts.push(name);
+        }
+        if let Some(v) = self.code {
+            rt.execute_script("deno:main", &v)?;
+            permissions.check_unstable_denials()?;
+            contents.scripts.push("deno:main".to_string());
+        }
+        if let Some(code) = self.warmup {
+            rt.execute_script("deno:warmup", &code)?;
+            futures::executor::block_on(rt.run_event_loop(false))
+                .context("Unable to run the warmup script")?;
+            permissions.check_unstable_denials()?;
+            contents.scripts.push("deno:warmup".to_string());
+        }
+
+        let snapshot = rt.snapshot();
+        let snapshot_slice: &[u8] = &*snapshot;
+        encode(snapshot_slice, contents, self.compression)
+    }
+}
+
+/// All the files under the base directory matched by the glob patterns, sorted
+/// by their paths.
+pub fn get_js_files(base_dir: &str, paths: &[&str]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for p in paths {
+        let pattern = Path::new(base_dir).join(p);
+        let pattern = pattern.to_string_lossy();
+        for entry in glob::glob(&pattern).with_context(|| format!("Invalid pattern {}", p))? {
+            files.push(entry?);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PermissionRequest;
+
+    #[test]
+    fn all_the_runtime_scripts_should_be_embedded() {
+        let files = get_js_files(env!("CARGO_MANIFEST_DIR"), &["js/**/*.js"]).unwrap();
+        let names: Vec<_> = files
+            .iter()
+            .map(|f| format!("js/{}", f.file_name().unwrap().to_string_lossy()))
+            .collect();
+        let embedded: Vec<_> = RUNTIME_JS
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        assert_eq!(embedded, names);
+    }
+
+    #[test]
+    fn denied_accesses_should_throw_permission_denied() {
+        // the warmup fails on an unhandled rejection
+        SnapshotBuilder::new()
+            .warmup(
+                r#"__bootstrap.fetch.fetch("https://example.com/").then(
+  () => { throw new Error("fetch should be denied"); },
+  (e) => {
+    if (!(e instanceof __bootstrap.errors.errors.PermissionDenied)) {
+      throw new Error(`unexpected error ${e.name}: ${e.message}`);
+    }
+  },
+);"#,
+            )
+            .build()
+            .unwrap();
+    }
+
+    #[test]
+    fn denied_unstable_apis_should_fail_the_build() {
+        let script = r#"Deno.core.opSync("op_sleep_sync", 1);"#;
+        let err = SnapshotBuilder::new()
+            .runtime_js(false)
+            .source("unstable.js", script)
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("Deno.sleepSync"));
+
+        SnapshotBuilder::new()
+            .runtime_js(false)
+            .source("unstable.js", script)
+            .permissions(PermissionPolicy::Allow(vec![PermissionRequest::Unstable(
+                "Deno.sleepSync".to_string(),
+            )]))
+            .build()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn build_should_work_in_async_context() {
+        // the timers need a tokio runtime while the script runs
+        let data = SnapshotBuilder::new()
+            .runtime_js(false)
+            .warmup("__bootstrap.timers.setTimeout(() => { globalThis.answer = 42; }, 1);")
+            .build()
+            .unwrap();
+        assert!(!data.is_empty());
+    }
 }
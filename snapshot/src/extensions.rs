@@ -0,0 +1,120 @@
+use deno_broadcast_channel::InMemoryBroadcastChannel;
+use deno_core::Extension;
+use deno_web::BlobStore;
+use std::fmt;
+
+use crate::permissions::Permissions;
+
+/// The built-in extensions which could be put into a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuiltinExtension {
+    Webidl,
+    Console,
+    Url,
+    Web,
+    Fetch,
+    Websocket,
+    Webstorage,
+    Crypto,
+    #[cfg(feature = "build_webgpu")]
+    Webgpu,
+    BroadcastChannel,
+    Tls,
+    Net,
+    Http,
+}
+
+impl BuiltinExtension {
+    /// All the built-in extensions in the order they're put into the default
+    /// snapshot. The runtime scripts under `js/` need all of them.
+    pub const ALL: &'static [BuiltinExtension] = &[
+        BuiltinExtension::Webidl,
+        BuiltinExtension::Console,
+        BuiltinExtension::Url,
+        BuiltinExtension::Web,
+        BuiltinExtension::Fetch,
+        BuiltinExtension::Websocket,
+        BuiltinExtension::Webstorage,
+        BuiltinExtension::Crypto,
+        #[cfg(feature = "build_webgpu")]
+        BuiltinExtension::Webgpu,
+        BuiltinExtension::BroadcastChannel,
+        BuiltinExtension::Tls,
+        BuiltinExtension::Net,
+        BuiltinExtension::Http,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinExtension::Webidl => "webidl",
+            BuiltinExtension::Console => "console",
+            BuiltinExtension::Url => "url",
+            BuiltinExtension::Web => "web",
+            BuiltinExtension::Fetch => "fetch",
+            BuiltinExtension::Websocket => "websocket",
+            BuiltinExtension::Webstorage => "webstorage",
+            BuiltinExtension::Crypto => "crypto",
+            #[cfg(feature = "build_webgpu")]
+            BuiltinExtension::Webgpu => "webgpu",
+            BuiltinExtension::BroadcastChannel => "broadcast_channel",
+            BuiltinExtension::Tls => "tls",
+            BuiltinExtension::Net => "net",
+            BuiltinExtension::Http => "http",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|e| e.name() == name).copied()
+    }
+
+    /// The extensions whose javascript this one uses, they have to be put into
+    /// the snapshot before it.
+    pub fn dependencies(&self) -> &'static [BuiltinExtension] {
+        use BuiltinExtension::*;
+        match self {
+            Webidl | Console | Tls => &[],
+            Url | Webstorage => &[Webidl],
+            Web => &[Webidl, Console, Url],
+            Fetch | Websocket => &[Webidl, Url, Web],
+            Crypto | BroadcastChannel => &[Webidl, Web],
+            #[cfg(feature = "build_webgpu")]
+            Webgpu => &[Webidl, Web],
+            Net => &[Web],
+            Http => &[Webidl, Web, Fetch, Websocket, Net],
+        }
+    }
+
+    pub(crate) fn init(&self) -> Extension {
+        match self {
+            BuiltinExtension::Webidl => deno_webidl::init(),
+            BuiltinExtension::Console => deno_console::init(),
+            BuiltinExtension::Url => deno_url::init(),
+            BuiltinExtension::Web => {
+                deno_web::init::<Permissions>(BlobStore::default(), Default::default())
+            }
+            BuiltinExtension::Fetch => deno_fetch::init::<Permissions>(Default::default()),
+            BuiltinExtension::Websocket => {
+                deno_websocket::init::<Permissions>("".to_owned(), None, None)
+            }
+            BuiltinExtension::Webstorage => deno_webstorage::init(None),
+            BuiltinExtension::Crypto => deno_crypto::init(None),
+            #[cfg(feature = "build_webgpu")]
+            BuiltinExtension::Webgpu => deno_webgpu::init(false),
+            BuiltinExtension::BroadcastChannel => {
+                deno_broadcast_channel::init(InMemoryBroadcastChannel::default(), false)
+            }
+            BuiltinExtension::Tls => deno_tls::init(),
+            BuiltinExtension::Net => deno_net::init::<Permissions>(
+                None, false, // No --unstable.
+                None,
+            ),
+            BuiltinExtension::Http => deno_http::init(),
+        }
+    }
+}
+
+impl fmt::Display for BuiltinExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -0,0 +1,280 @@
+//! The container of a snapshot:
+//!
+//! ```text
+//! magic (8 bytes) | format version (u32 le) | meta length (u32 le) | meta (json) | payload
+//! ```
+//!
+//! The meta records what the snapshot was built with and the checksum of the
+//! payload, so that a truncated or incompatible snapshot is rejected before it
+//! gets to V8.
+
+use deno_core::{serde_json, v8};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const MAGIC: &[u8; 8] = b"DENOSNAP";
+const HEADER_LEN: usize = MAGIC.len() + 4 + 4;
+
+/// Version of the container format, bumped on incompatible changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Version of deno_core the snapshots are built with, a test checks it against
+/// the version locked in Cargo.lock.
+pub const DENO_CORE_VERSION: &str = "0.147.0";
+
+/// How the payload of a snapshot is compressed. Uncompressed or lz4 snapshots
+/// are bigger, but faster to decode at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Zstd { level: i32 },
+    Lz4,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Zstd { level: 7 }
+    }
+}
+
+/// What was put into a snapshot.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotContents {
+    /// names of the built-in extensions in the snapshot
+    pub extensions: Vec<String>,
+    /// names of the executed scripts, in order
+    #[serde(default)]
+    pub scripts: Vec<String>,
+}
+
+/// What a snapshot was built with, stored in its header.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotMeta {
+    pub v8_version: String,
+    pub deno_core_version: String,
+    #[serde(flatten)]
+    pub contents: SnapshotContents,
+    pub compression: Compression,
+    /// crc32 of the payload
+    pub checksum: u32,
+    /// size of the uncompressed snapshot
+    pub size: usize,
+}
+
+/// The reasons a snapshot couldn't be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// the data doesn't start with the magic bytes, e.g. it isn't a snapshot,
+    /// or it was created before the container format was introduced
+    InvalidMagic,
+    UnsupportedFormat(u32),
+    /// the data is shorter than the header says
+    Truncated,
+    InvalidMeta(String),
+    V8Mismatch {
+        expected: String,
+        found: String,
+    },
+    DenoCoreMismatch {
+        expected: String,
+        found: String,
+    },
+    ChecksumMismatch {
+        expected: u32,
+        found: u32,
+    },
+    Decompress(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidMagic => write!(f, "Not a snapshot, invalid magic bytes."),
+            SnapshotError::UnsupportedFormat(v) => write!(
+                f,
+                "Unsupported snapshot format version {}, expected {}.",
+                v, FORMAT_VERSION
+            ),
+            SnapshotError::Truncated => write!(f, "The snapshot is truncated."),
+            SnapshotError::InvalidMeta(e) => write!(f, "Invalid snapshot header: {}", e),
+            SnapshotError::V8Mismatch { expected, found } => write!(
+                f,
+                "The snapshot is built with V8 {}, but the runtime uses V8 {}.",
+                found, expected
+            ),
+            SnapshotError::DenoCoreMismatch { expected, found } => write!(
+                f,
+                "The snapshot is built with deno_core {}, but the runtime uses deno_core {}.",
+                found, expected
+            ),
+            SnapshotError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "Snapshot checksum mismatch, expected {:08x}, found {:08x}.",
+                expected, found
+            ),
+            SnapshotError::Decompress(e) => write!(f, "Unable to decompress the snapshot: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// Compress the snapshot and put it into the container with its meta.
+pub fn encode(
+    snapshot: &[u8],
+    contents: SnapshotContents,
+    compression: Compression,
+) -> std::io::Result<Vec<u8>> {
+    let payload = match compression {
+        Compression::None => snapshot.to_vec(),
+        Compression::Zstd { level } => zstd::encode_all(snapshot, level)?,
+        Compression::Lz4 => lz4_flex::compress(snapshot),
+    };
+    let meta = SnapshotMeta {
+        v8_version: v8::V8::get_version().to_string(),
+        deno_core_version: DENO_CORE_VERSION.to_string(),
+        contents,
+        compression,
+        checksum: crc32fast::hash(&payload),
+        size: snapshot.len(),
+    };
+    let meta = serde_json::to_vec(&meta)?;
+
+    let mut data = Vec::with_capacity(HEADER_LEN + meta.len() + payload.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    data.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+    data.extend_from_slice(&meta);
+    data.extend_from_slice(&payload);
+    Ok(data)
+}
+
+/// Read the meta of the snapshot without checking or decompressing it.
+pub fn decode_meta(data: &[u8]) -> Result<SnapshotMeta, SnapshotError> {
+    split(data).map(|(meta, _)| meta)
+}
+
+/// Check the snapshot against the running V8 and deno_core, and return the
+/// uncompressed snapshot.
+pub fn decode(data: &[u8]) -> Result<Box<[u8]>, SnapshotError> {
+    let (meta, payload) = split(data)?;
+    let v8_version = v8::V8::get_version();
+    if meta.v8_version != v8_version {
+        return Err(SnapshotError::V8Mismatch {
+            expected: v8_version.to_string(),
+            found: meta.v8_version,
+        });
+    }
+    if meta.deno_core_version != DENO_CORE_VERSION {
+        return Err(SnapshotError::DenoCoreMismatch {
+            expected: DENO_CORE_VERSION.to_string(),
+            found: meta.deno_core_version,
+        });
+    }
+    let checksum = crc32fast::hash(payload);
+    if checksum != meta.checksum {
+        return Err(SnapshotError::ChecksumMismatch {
+            expected: meta.checksum,
+            found: checksum,
+        });
+    }
+
+    let snapshot = match meta.compression {
+        Compression::None => payload.to_vec(),
+        Compression::Zstd { .. } => {
+            zstd::decode_all(payload).map_err(|e| SnapshotError::Decompress(e.to_string()))?
+        }
+        Compression::Lz4 => lz4_flex::decompress(payload, meta.size)
+            .map_err(|e| SnapshotError::Decompress(e.to_string()))?,
+    };
+    if snapshot.len() != meta.size {
+        return Err(SnapshotError::Truncated);
+    }
+    Ok(snapshot.into_boxed_slice())
+}
+
+fn split(data: &[u8]) -> Result<(SnapshotMeta, &[u8]), SnapshotError> {
+    if !data.starts_with(MAGIC) {
+        return Err(SnapshotError::InvalidMagic);
+    }
+    if data.len() < HEADER_LEN {
+        return Err(SnapshotError::Truncated);
+    }
+    let read_u32 = |offset: usize| {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(&data[offset..offset + 4]);
+        u32::from_le_bytes(buf)
+    };
+    let version = read_u32(MAGIC.len());
+    if version != FORMAT_VERSION {
+        return Err(SnapshotError::UnsupportedFormat(version));
+    }
+    let meta_len = read_u32(MAGIC.len() + 4) as usize;
+    let rest = &data[HEADER_LEN..];
+    if rest.len() < meta_len {
+        return Err(SnapshotError::Truncated);
+    }
+    let (meta, payload) = rest.split_at(meta_len);
+    let meta =
+        serde_json::from_slice(meta).map_err(|e| SnapshotError::InvalidMeta(e.to_string()))?;
+    Ok((meta, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deno_core_version_should_match_the_dependency() {
+        let lock = concat!(env!("CARGO_MANIFEST_DIR"), "/../Cargo.lock");
+        let lock = std::fs::read_to_string(lock).unwrap();
+        let versions: Vec<_> = lock
+            .split("[[package]]")
+            .filter(|p| p.contains("\nname = \"deno_core\"\n"))
+            .filter_map(|p| p.lines().find_map(|l| l.strip_prefix("version = ")))
+            .map(|v| v.trim_matches('"'))
+            .collect();
+        assert_eq!(versions, vec![DENO_CORE_VERSION]);
+    }
+
+    #[test]
+    fn encode_decode_should_work() {
+        let snapshot = b"not really a snapshot".repeat(100);
+        let compressions = [
+            Compression::None,
+            Compression::Zstd { level: 3 },
+            Compression::Lz4,
+        ];
+        for compression in compressions {
+            let contents = SnapshotContents {
+                extensions: vec!["console".to_string()],
+                scripts: vec!["deno:main".to_string()],
+            };
+            let data = encode(&snapshot, contents.clone(), compression).unwrap();
+            let meta = decode_meta(&data).unwrap();
+            assert_eq!(meta.contents, contents);
+            assert_eq!(meta.compression, compression);
+            assert_eq!(meta.size, snapshot.len());
+            assert_eq!(&*decode(&data).unwrap(), &snapshot[..]);
+        }
+    }
+
+    #[test]
+    fn decode_should_reject_invalid_data() {
+        let snapshot = b"not really a snapshot".repeat(100);
+        let data = encode(&snapshot, Default::default(), Compression::default()).unwrap();
+
+        assert_eq!(decode(b"garbage"), Err(SnapshotError::InvalidMagic));
+        assert_eq!(decode(&data[..10]), Err(SnapshotError::Truncated));
+        assert!(matches!(
+            decode(&data[..data.len() - 1]),
+            Err(SnapshotError::ChecksumMismatch { .. })
+        ));
+
+        let mut data = data;
+        data[MAGIC.len()] = 99;
+        assert_eq!(decode(&data), Err(SnapshotError::UnsupportedFormat(99)));
+    }
+}
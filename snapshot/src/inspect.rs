@@ -0,0 +1,110 @@
+use deno_core::{
+    anyhow::{anyhow, Result},
+    serde_v8, v8, Extension, JsRuntime, RuntimeOptions, Snapshot,
+};
+use serde::Deserialize;
+
+use crate::{decode, decode_meta, BuiltinExtension, SnapshotMeta};
+
+/// What ended up in a snapshot, see [inspect_snapshot].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    /// the meta in the header, with the extensions and the executed scripts
+    pub meta: SnapshotMeta,
+    /// names of the own properties of `globalThis`, sorted
+    pub globals: Vec<String>,
+    /// names of the registered ops, sorted
+    pub ops: Vec<String>,
+    /// size of the uncompressed snapshot
+    pub size: usize,
+    /// size of the snapshot with its header
+    pub compressed_size: usize,
+}
+
+#[derive(Deserialize)]
+struct JsInfo {
+    globals: Vec<String>,
+    ops: Vec<String>,
+}
+
+const INSPECT_CODE: &str = r#"({
+  globals: Object.getOwnPropertyNames(globalThis).sort(),
+  ops: Object.keys(globalThis.Deno?.core?.ops ?? {}).sort(),
+})"#;
+
+/// Start the snapshot in a throwaway runtime and report what's in it. The
+/// built-in extensions are restored from the header, `extensions` are the
+/// custom ones the snapshot was built with, as the ops have to match.
+pub fn inspect_snapshot(data: &[u8], extensions: Vec<Extension>) -> Result<SnapshotInfo> {
+    let meta = decode_meta(data)?;
+    let snapshot = decode(data)?;
+    let size = snapshot.len();
+
+    let mut all_extensions = meta
+        .contents
+        .extensions
+        .iter()
+        .map(|name| {
+            BuiltinExtension::from_name(name)
+                .map(|e| e.init())
+                .ok_or_else(|| anyhow!("Unknown extension \"{}\" in the snapshot.", name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    all_extensions.extend(extensions);
+
+    let mut rt = JsRuntime::new(RuntimeOptions {
+        startup_snapshot: Some(Snapshot::Boxed(snapshot)),
+        extensions: all_extensions,
+        ..Default::default()
+    });
+    let value = rt.execute_script("deno:inspect", INSPECT_CODE)?;
+    let scope = &mut rt.handle_scope();
+    let value = v8::Local::new(scope, value);
+    let info: JsInfo = serde_v8::from_v8(scope, value)?;
+
+    Ok(SnapshotInfo {
+        meta,
+        globals: info.globals,
+        ops: info.ops,
+        size,
+        compressed_size: data.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SnapshotBuilder;
+
+    #[test]
+    fn inspect_snapshot_should_report_the_contents() {
+        let data = SnapshotBuilder::new()
+            .builtins([
+                BuiltinExtension::Webidl,
+                BuiltinExtension::Console,
+                BuiltinExtension::Url,
+            ])
+            .runtime_js(false)
+            .source("globals.js", "globalThis.answer = 42;")
+            .build()
+            .unwrap();
+        let info = inspect_snapshot(&data, vec![]).unwrap();
+
+        assert_eq!(
+            info.meta.contents.extensions,
+            vec!["webidl", "console", "url"]
+        );
+        assert_eq!(
+            info.meta.contents.scripts,
+            vec!["deno:snapshot_permissions.js", "deno:globals.js"]
+        );
+        assert!(info.globals.contains(&"answer".to_string()));
+        assert!(info.globals.contains(&"__bootstrap".to_string()));
+        assert!(info.ops.contains(&"op_url_parse".to_string()));
+        // the extensions which are not in the snapshot don't register ops
+        assert!(!info.ops.iter().any(|op| op.starts_with("op_fetch")));
+        assert_eq!(info.size, info.meta.size);
+        assert_eq!(info.compressed_size, data.len());
+        assert!(info.compressed_size < info.size);
+    }
+}
@@ -1,11 +1,24 @@
 #[cfg(feature = "build")]
 mod builder;
 #[cfg(feature = "build")]
+mod extensions;
+mod format;
+#[cfg(feature = "build")]
+mod inspect;
+#[cfg(feature = "build")]
 mod permissions;
 
 #[cfg(feature = "build")]
-pub use builder::{create_snapshot, create_snapshot_with_main_module, get_js_files};
-
-pub fn decode(compressed: &[u8]) -> Box<[u8]> {
-    zstd::decode_all(compressed).unwrap().into_boxed_slice()
-}
+pub use builder::{
+    create_snapshot, create_snapshot_with_main_module, get_js_files, SnapshotBuilder,
+};
+#[cfg(feature = "build")]
+pub use extensions::BuiltinExtension;
+pub use format::{
+    decode, decode_meta, encode, Compression, SnapshotContents, SnapshotError, SnapshotMeta,
+    DENO_CORE_VERSION, FORMAT_VERSION,
+};
+#[cfg(feature = "build")]
+pub use inspect::{inspect_snapshot, SnapshotInfo};
+#[cfg(feature = "build")]
+pub use permissions::{PermissionLog, PermissionPolicy, PermissionRequest};
@@ -1,55 +1,320 @@
+use deno_core::error::{custom_error, get_custom_error_class, AnyError};
 use deno_net::NetPermissions;
 use deno_web::TimersPermission;
 use deno_websocket::WebSocketPermissions;
-use std::path::Path;
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
-pub struct Permissions;
+/// An access to a permission guarded API while the snapshot is built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermissionRequest {
+    /// a host with an optional port, the host without a port allows any port
+    Net(String, Option<u16>),
+    /// a path, which allows everything under it
+    Read(PathBuf),
+    Write(PathBuf),
+    Hrtime,
+    /// an unstable API by its name, e.g. `Deno.sleepSync`
+    Unstable(String),
+}
+
+impl PermissionRequest {
+    /// Whether the request is allowed by this one.
+    fn allows(&self, request: &PermissionRequest) -> bool {
+        match (self, request) {
+            (PermissionRequest::Net(host, port), PermissionRequest::Net(h, p)) => {
+                host == h && (port.is_none() || port == p)
+            }
+            (PermissionRequest::Read(path), PermissionRequest::Read(p)) => is_under(p, path),
+            (PermissionRequest::Write(path), PermissionRequest::Write(p)) => is_under(p, path),
+            (PermissionRequest::Hrtime, PermissionRequest::Hrtime) => true,
+            (PermissionRequest::Unstable(name), PermissionRequest::Unstable(n)) => name == n,
+            _ => false,
+        }
+    }
+}
+
+/// Whether the path is the base or under it, once their `.` and `..`
+/// components are resolved, so that `/data/../etc` isn't under `/data`.
+fn is_under(path: &Path, base: &Path) -> bool {
+    match (normalize_path(path), normalize_path(base)) {
+        (Some(path), Some(base)) => path.starts_with(base),
+        _ => false,
+    }
+}
+
+/// Resolve the `.` and `..` components of the path lexically, `None` if it
+/// goes above its root or its start.
+fn normalize_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) {
+                    return None;
+                }
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    Some(normalized)
+}
+
+impl fmt::Display for PermissionRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PermissionRequest::Net(host, Some(port)) => {
+                write!(f, "net access to {}:{}", host, port)
+            }
+            PermissionRequest::Net(host, None) => write!(f, "net access to {}", host),
+            PermissionRequest::Read(path) => write!(f, "read access to {}", path.display()),
+            PermissionRequest::Write(path) => write!(f, "write access to {}", path.display()),
+            PermissionRequest::Hrtime => write!(f, "high resolution time"),
+            PermissionRequest::Unstable(name) => write!(f, "unstable API {}", name),
+        }
+    }
+}
+
+/// The class of the permission errors thrown into the scripts. The error
+/// classes of the runtime are only registered when it bootstraps, registering
+/// `PermissionDenied` while snapshotting would make the bootstrap fail, so it
+/// has a class of its own, registered by [PERMISSIONS_JS].
+const PERMISSION_DENIED_CLASS: &str = "SnapshotPermissionDenied";
+
+/// Registers [PERMISSION_DENIED_CLASS], it builds the `PermissionDenied` error
+/// of the runtime scripts if they're in the snapshot.
+pub(crate) const PERMISSIONS_JS: &str = r#"((window) => {
+  class PermissionDenied extends Error {
+    constructor(msg) {
+      super(msg);
+      this.name = "PermissionDenied";
+    }
+  }
+  const errorClass = window.__bootstrap?.errors?.errors.PermissionDenied ??
+    PermissionDenied;
+  Deno.core.registerErrorClass("SnapshotPermissionDenied", errorClass);
+})(globalThis);
+"#;
+
+/// The `get_error_class_fn` of the snapshot runtime, so that the scripts could
+/// tell the permission errors apart.
+pub(crate) fn get_error_class(e: &AnyError) -> &'static str {
+    match get_custom_error_class(e) {
+        Some("PermissionDenied") => PERMISSION_DENIED_CLASS,
+        Some(
+            class @ ("RangeError" | "ReferenceError" | "SyntaxError" | "TypeError" | "URIError"),
+        ) => class,
+        _ => "Error",
+    }
+}
+
+/// The accesses recorded by [PermissionPolicy::Record], clones share the same
+/// records.
+#[derive(Debug, Clone, Default)]
+pub struct PermissionLog(Arc<Mutex<Vec<PermissionRequest>>>);
+
+impl PermissionLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn requests(&self) -> Vec<PermissionRequest> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn push(&self, request: PermissionRequest) {
+        self.0.lock().unwrap().push(request);
+    }
+}
+
+/// How the permission guarded APIs, e.g. fetch, net and timers, behave while
+/// the snapshot is built.
+#[derive(Debug, Clone)]
+pub enum PermissionPolicy {
+    /// deny all the accesses, the scripts get a `PermissionDenied` error
+    Deny,
+    /// allow the listed accesses only, deny the others
+    Allow(Vec<PermissionRequest>),
+    /// deny all the accesses like [PermissionPolicy::Deny], and record them
+    Record(PermissionLog),
+}
+
+impl Default for PermissionPolicy {
+    fn default() -> Self {
+        PermissionPolicy::Deny
+    }
+}
+
+/// The permissions put into the op state of the snapshot runtime. Clones
+/// share the denied unstable APIs.
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    policy: PermissionPolicy,
+    denied_unstable: Arc<Mutex<Vec<String>>>,
+}
+
+impl Permissions {
+    pub fn new(policy: PermissionPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Fail if an unstable API was used without being allowed. Unlike the
+    /// other checks, the unstable one can't throw into the script, so the
+    /// denial is recorded and the build fails afterwards.
+    pub(crate) fn check_unstable_denials(&self) -> Result<(), AnyError> {
+        match self.denied_unstable.lock().unwrap().first() {
+            Some(name) => Err(custom_error(
+                "PermissionDenied",
+                format!(
+                    "Requires {} while snapshotting",
+                    PermissionRequest::Unstable(name.clone())
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn is_allowed(&self, request: PermissionRequest) -> bool {
+        match &self.policy {
+            PermissionPolicy::Deny => false,
+            PermissionPolicy::Allow(allowed) => allowed.iter().any(|a| a.allows(&request)),
+            PermissionPolicy::Record(log) => {
+                log.push(request);
+                false
+            }
+        }
+    }
+
+    fn check(&self, request: PermissionRequest) -> Result<(), AnyError> {
+        let message = format!("Requires {} while snapshotting", request);
+        if self.is_allowed(request) {
+            Ok(())
+        } else {
+            Err(custom_error("PermissionDenied", message))
+        }
+    }
+
+    fn check_url(&self, url: &deno_core::url::Url) -> Result<(), AnyError> {
+        let host = url.host_str().unwrap_or_default().to_string();
+        self.check(PermissionRequest::Net(host, url.port_or_known_default()))
+    }
+}
 
 impl deno_fetch::FetchPermissions for Permissions {
-    fn check_net_url(
-        &mut self,
-        _url: &deno_core::url::Url,
-    ) -> Result<(), deno_core::error::AnyError> {
-        unreachable!("snapshotting!")
+    fn check_net_url(&mut self, url: &deno_core::url::Url) -> Result<(), AnyError> {
+        self.check_url(url)
     }
 
-    fn check_read(&mut self, _p: &Path) -> Result<(), deno_core::error::AnyError> {
-        unreachable!("snapshotting!")
+    fn check_read(&mut self, p: &Path) -> Result<(), AnyError> {
+        self.check(PermissionRequest::Read(p.to_path_buf()))
     }
 }
 
 impl WebSocketPermissions for Permissions {
-    fn check_net_url(
-        &mut self,
-        _url: &deno_core::url::Url,
-    ) -> Result<(), deno_core::error::AnyError> {
-        unreachable!("snapshotting!")
+    fn check_net_url(&mut self, url: &deno_core::url::Url) -> Result<(), AnyError> {
+        self.check_url(url)
     }
 }
 
 impl TimersPermission for Permissions {
     fn allow_hrtime(&mut self) -> bool {
-        unreachable!("snapshotting!")
+        self.is_allowed(PermissionRequest::Hrtime)
     }
 
-    fn check_unstable(&self, _state: &deno_core::OpState, _api_name: &'static str) {
-        unreachable!("snapshotting!")
+    fn check_unstable(&self, _state: &deno_core::OpState, api_name: &'static str) {
+        if !self.is_allowed(PermissionRequest::Unstable(api_name.to_string())) {
+            self.denied_unstable
+                .lock()
+                .unwrap()
+                .push(api_name.to_string());
+        }
     }
 }
 
 impl NetPermissions for Permissions {
-    fn check_net<T: AsRef<str>>(
-        &mut self,
-        _host: &(T, Option<u16>),
-    ) -> Result<(), deno_core::error::AnyError> {
-        unreachable!("snapshotting!")
+    fn check_net<T: AsRef<str>>(&mut self, host: &(T, Option<u16>)) -> Result<(), AnyError> {
+        self.check(PermissionRequest::Net(host.0.as_ref().to_string(), host.1))
     }
 
-    fn check_read(&mut self, _p: &Path) -> Result<(), deno_core::error::AnyError> {
-        unreachable!("snapshotting!")
+    fn check_read(&mut self, p: &Path) -> Result<(), AnyError> {
+        self.check(PermissionRequest::Read(p.to_path_buf()))
     }
 
-    fn check_write(&mut self, _p: &Path) -> Result<(), deno_core::error::AnyError> {
-        unreachable!("snapshotting!")
+    fn check_write(&mut self, p: &Path) -> Result<(), AnyError> {
+        self.check(PermissionRequest::Write(p.to_path_buf()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permission_errors_should_have_their_own_class() {
+        let err = Permissions::default()
+            .check(PermissionRequest::Hrtime)
+            .unwrap_err();
+        assert_eq!(get_error_class(&err), PERMISSION_DENIED_CLASS);
+        let err = deno_core::error::type_error("invalid");
+        assert_eq!(get_error_class(&err), "TypeError");
+        let err = deno_core::anyhow::anyhow!("failed");
+        assert_eq!(get_error_class(&err), "Error");
+    }
+
+    #[test]
+    fn permissions_should_follow_the_policy() {
+        let mut perms = Permissions::new(PermissionPolicy::Deny);
+        assert!(perms.check_net(&("example.com", Some(443))).is_err());
+        assert!(!perms.allow_hrtime());
+
+        let mut perms = Permissions::new(PermissionPolicy::Allow(vec![
+            PermissionRequest::Net("example.com".to_string(), None),
+            PermissionRequest::Read(PathBuf::from("/data")),
+        ]));
+        assert!(perms.check_net(&("example.com", Some(443))).is_ok());
+        assert!(perms.check_net(&("example.org", Some(443))).is_err());
+        assert!(NetPermissions::check_read(&mut perms, Path::new("/data/a.txt")).is_ok());
+        assert!(NetPermissions::check_read(&mut perms, Path::new("/etc/passwd")).is_err());
+        assert!(perms.check_write(Path::new("/data/a.txt")).is_err());
+        assert!(NetPermissions::check_read(&mut perms, Path::new("/data/./b/../a.txt")).is_ok());
+        assert!(NetPermissions::check_read(&mut perms, Path::new("/data/../etc/passwd")).is_err());
+        assert!(NetPermissions::check_read(&mut perms, Path::new("/../data/a.txt")).is_err());
+
+        let perms = Permissions::new(PermissionPolicy::Allow(vec![PermissionRequest::Unstable(
+            "Deno.sleepSync".to_string(),
+        )]));
+        let rt = deno_core::JsRuntime::new(Default::default());
+        let state = rt.op_state();
+        let state = state.borrow();
+        perms.check_unstable(&state, "Deno.sleepSync");
+        assert!(perms.check_unstable_denials().is_ok());
+        // a clone, like the one in the op state, shares the denials
+        perms.clone().check_unstable(&state, "Deno.umask");
+        let err = perms.check_unstable_denials().unwrap_err();
+        assert_eq!(get_error_class(&err), PERMISSION_DENIED_CLASS);
+        assert!(err.to_string().contains("Deno.umask"));
+
+        let log = PermissionLog::new();
+        let mut perms = Permissions::new(PermissionPolicy::Record(log.clone()));
+        assert!(perms.check_net(&("example.com", Some(443))).is_err());
+        assert!(!perms.allow_hrtime());
+        assert_eq!(
+            log.requests(),
+            vec![
+                PermissionRequest::Net("example.com".to_string(), Some(443)),
+                PermissionRequest::Hrtime
+            ]
+        );
     }
 }
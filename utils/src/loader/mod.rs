@@ -13,7 +13,17 @@ pub struct UniversalModuleLoader {
 }
 
 pub async fn get_source_code(m: &ModuleSpecifier) -> Result<String, AnyError> {
-    let code = match m.scheme() {
+    let bytes = get_source_bytes(m).await?;
+    match String::from_utf8(bytes) {
+        Ok(code) => Ok(code),
+        Err(_) => bail!("Invalid utf-8 source code: {}", m),
+    }
+}
+
+/// Get the raw content of the specifier, which could be a http(s), file or data
+/// url. Unlike `get_source_code`, the content is not required to be utf-8.
+pub async fn get_source_bytes(m: &ModuleSpecifier) -> Result<Vec<u8>, AnyError> {
+    let bytes = match m.scheme() {
         "http" | "https" => {
             let client = reqwest::Client::builder()
                 .timeout(Duration::from_millis(5000))
@@ -23,30 +33,26 @@ pub async fn get_source_code(m: &ModuleSpecifier) -> Result<String, AnyError> {
             // 200-299, but `error_for_status()` fails if the status is
             // 400-599.
             let res = res.error_for_status()?;
-            res.text().await?
+            res.bytes().await?.to_vec()
         }
         "file" => {
             let path = match m.to_file_path() {
                 Ok(path) => path,
                 Err(_) => bail!("Invalid file URL."),
             };
-            tokio::fs::read_to_string(path).await?
+            tokio::fs::read(path).await?
         }
         "data" => {
             let url = match DataUrl::process(m.as_str()) {
                 Ok(url) => url,
                 Err(_) => bail!("Not a valid data URL."),
             };
-            let bytes = match url.decode_to_vec() {
+            match url.decode_to_vec() {
                 Ok((bytes, _)) => bytes,
                 Err(_) => bail!("Not a valid data URL."),
-            };
-            match String::from_utf8(bytes) {
-                Ok(s) => s,
-                Err(_) => bail!("Not a valid data URL code."),
             }
         }
         schema => bail!("Invalid schema {}", schema),
     };
-    Ok(code)
+    Ok(bytes)
 }
@@ -16,7 +16,7 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 use crate::FsModuleStore;
-use crate::{get_source_code, ModuleStore, UniversalModuleLoader};
+use crate::{get_source_bytes, get_source_code, ModuleStore, UniversalModuleLoader};
 
 impl Default for UniversalModuleLoader {
     fn default() -> Self {
@@ -51,6 +51,19 @@ impl UniversalModuleLoader {
         }
         Ok(code)
     }
+
+    /// Same as `get_and_update_source`, but the raw content is returned and
+    /// stored as is, e.g. for binary assets like wasm.
+    pub async fn get_and_update_source_bytes(
+        self,
+        m: &ModuleSpecifier,
+    ) -> Result<Vec<u8>, AnyError> {
+        let bytes = get_source_bytes(m).await?;
+        if let Some(store) = self.store.as_ref() {
+            store.put(m.to_string(), &bytes).await?;
+        }
+        Ok(bytes)
+    }
 }
 
 impl ModuleLoader for UniversalModuleLoader {
@@ -153,4 +166,20 @@ mod tests {
         let cache = store.get(m.as_str()).await.unwrap();
         assert_eq!(cache, expected.as_bytes().to_vec().into_boxed_slice());
     }
+
+    #[tokio::test]
+    async fn universal_loader_should_store_the_raw_bytes() {
+        let dir = std::env::temp_dir().join("deno_universal_loader_bytes");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.bin");
+        std::fs::write(&path, [0u8, 0x61, 0xff]).unwrap();
+        let m = ModuleSpecifier::from_file_path(&path).unwrap();
+        let store = FsModuleStore::new(dir.join("store"));
+        let loader = UniversalModuleLoader::new(Some(Arc::new(store.clone())), true);
+        let bytes = loader.get_and_update_source_bytes(&m).await.unwrap();
+        assert_eq!(bytes, vec![0u8, 0x61, 0xff]);
+
+        let cache = store.get(m.as_str()).await.unwrap();
+        assert_eq!(&*cache, &[0u8, 0x61, 0xff]);
+    }
 }